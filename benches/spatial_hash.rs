@@ -0,0 +1,86 @@
+//! Compares the [`SpatialHash`] broad phase with the nested loops it replaced.
+//!
+//! Run with `cargo bench --bench spatial_hash`.
+
+use std::{hint::black_box, time::Instant};
+
+use bevy::prelude::*;
+use circles_madness::spatial_hash::SpatialHash;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const ITERATIONS: u32 = 10;
+
+/// Targets and bullets scattered over the default arena, like a crowded late game.
+fn scatter(count: usize, radius: f32, rng: &mut ChaCha8Rng) -> Vec<(Entity, Vec2, f32)> {
+    (0..count)
+        .map(|i| {
+            let position = Vec2::new(
+                rng.gen_range(-768f32..768f32),
+                rng.gen_range(-512f32..512f32),
+            );
+            (Entity::from_raw(i as u32), position, radius)
+        })
+        .collect()
+}
+
+fn nested_loops(bullets: &[(Entity, Vec2, f32)], targets: &[(Entity, Vec2, f32)]) -> usize {
+    bullets
+        .iter()
+        .map(|(_, bullet, bullet_radius)| {
+            targets
+                .iter()
+                .filter(|(_, target, target_radius)| {
+                    bullet.distance(*target) < bullet_radius + target_radius
+                })
+                .count()
+        })
+        .sum()
+}
+
+fn spatial_hash(
+    grid: &mut SpatialHash,
+    bullets: &[(Entity, Vec2, f32)],
+    targets: &[(Entity, Vec2, f32)],
+) -> usize {
+    grid.clear();
+    for (entity, position, radius) in targets.iter() {
+        grid.insert(*entity, *position, *radius);
+    }
+    bullets
+        .iter()
+        .map(|(_, bullet, radius)| grid.query(*bullet, *radius).count())
+        .sum()
+}
+
+fn time(mut f: impl FnMut() -> usize) -> (f64, usize) {
+    let start = Instant::now();
+    let mut hits = 0;
+    for _ in 0..ITERATIONS {
+        hits = black_box(f());
+    }
+    (
+        start.elapsed().as_secs_f64() * 1000f64 / ITERATIONS as f64,
+        hits,
+    )
+}
+
+fn main() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut grid = SpatialHash::default();
+    println!("entities  nested loops  spatial hash  speedup");
+    for count in [250, 1000, 4000, 16000] {
+        let targets = scatter(count / 2, 18f32, &mut rng);
+        let bullets = scatter(count / 2, 2f32, &mut rng);
+        let (nested_ms, nested_hits) = time(|| nested_loops(&bullets, &targets));
+        let (grid_ms, grid_hits) = time(|| spatial_hash(&mut grid, &bullets, &targets));
+        assert_eq!(
+            nested_hits, grid_hits,
+            "both broad phases must find the same hits"
+        );
+        println!(
+            "{count:>8}  {nested_ms:>9.3} ms  {grid_ms:>9.3} ms  {:>6.1}x",
+            nested_ms / grid_ms
+        );
+    }
+}
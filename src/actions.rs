@@ -0,0 +1,528 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::{input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{gamepad::ActiveGamepad, network::OnlineSession, storage, GameMode};
+
+const STORAGE_NAME: &str = "bindings";
+
+/// Maps the keyboard, mouse and gamepad buttons to [`Action`]s, through the [`Bindings`].
+///
+/// Gameplay and menus only look at the [`ActionState`], never at the devices directly.
+/// Pointing with the mouse and the sticks isn't rebindable, see [`crate::gamepad`].
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Bindings::load())
+            .init_resource::<ActionState>()
+            .init_resource::<LocalPlayers>()
+            .add_systems(PreUpdate, update_action_state.after(InputSystem));
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    /// Aiming with buttons also fires, like the right stick.
+    AimUp,
+    AimDown,
+    AimLeft,
+    AimRight,
+    /// Fires at the mouse cursor, or where the right stick last pointed.
+    Fire,
+    Dash,
+    Pause,
+    Confirm,
+    Back,
+    /// Resets the selected action to its default bindings, in the controls menu.
+    ResetBinding,
+    /// Places the tool of the arena editor at the mouse cursor, or the center of the view
+    /// from the gamepad.
+    Place,
+    /// Removes what is under the mouse cursor in the arena editor, or at the center of the view.
+    Remove,
+    /// The next thing the arena editor places.
+    NextTool,
+    /// Whether the obstacles placed by the editor absorb or reflect the bullets.
+    ToggleBullets,
+    /// Places the rects of the editor along the other axis.
+    RotateRect,
+    SaveArena,
+    LoadArena,
+}
+
+impl Action {
+    pub const ALL: [Action; 21] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::AimUp,
+        Action::AimDown,
+        Action::AimLeft,
+        Action::AimRight,
+        Action::Fire,
+        Action::Dash,
+        Action::Pause,
+        Action::Confirm,
+        Action::Back,
+        Action::ResetBinding,
+        Action::Place,
+        Action::Remove,
+        Action::NextTool,
+        Action::ToggleBullets,
+        Action::RotateRect,
+        Action::SaveArena,
+        Action::LoadArena,
+    ];
+
+    /// The menus can't be used without these, see [`Bindings::menus_usable`].
+    pub const MENU: [Action; 4] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Confirm,
+        Action::Back,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// A button of the [`ActiveGamepad`].
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    /// Rebinding an action replaces its bindings of the same device.
+    pub fn same_device(&self, other: &Binding) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::Mouse(button) => format!("Mouse {button:?}"),
+            Binding::Gamepad(button_type) => format!("Pad {button_type:?}"),
+        }
+    }
+}
+
+/// Saved after each change, the defaults are used when nothing was saved.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bindings(pub BTreeMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::*;
+        Self(BTreeMap::from([
+            (
+                Action::MoveUp,
+                vec![
+                    Key(KeyCode::W),
+                    Key(KeyCode::Up),
+                    Gamepad(GamepadButtonType::DPadUp),
+                ],
+            ),
+            (
+                Action::MoveDown,
+                vec![
+                    Key(KeyCode::S),
+                    Key(KeyCode::Down),
+                    Gamepad(GamepadButtonType::DPadDown),
+                ],
+            ),
+            (
+                Action::MoveLeft,
+                vec![
+                    Key(KeyCode::A),
+                    Key(KeyCode::Left),
+                    Gamepad(GamepadButtonType::DPadLeft),
+                ],
+            ),
+            (
+                Action::MoveRight,
+                vec![
+                    Key(KeyCode::D),
+                    Key(KeyCode::Right),
+                    Gamepad(GamepadButtonType::DPadRight),
+                ],
+            ),
+            (Action::AimUp, vec![]),
+            (Action::AimDown, vec![]),
+            (Action::AimLeft, vec![]),
+            (Action::AimRight, vec![]),
+            (
+                Action::Fire,
+                vec![
+                    Mouse(MouseButton::Left),
+                    Gamepad(GamepadButtonType::RightTrigger2),
+                ],
+            ),
+            (
+                Action::Dash,
+                vec![
+                    Key(KeyCode::Space),
+                    Mouse(MouseButton::Right),
+                    Gamepad(GamepadButtonType::LeftTrigger2),
+                ],
+            ),
+            (
+                Action::Pause,
+                vec![Key(KeyCode::P), Gamepad(GamepadButtonType::Start)],
+            ),
+            (
+                Action::Confirm,
+                vec![
+                    Key(KeyCode::Return),
+                    Gamepad(GamepadButtonType::South),
+                    Gamepad(GamepadButtonType::Start),
+                ],
+            ),
+            (
+                Action::Back,
+                vec![
+                    Key(KeyCode::Escape),
+                    Gamepad(GamepadButtonType::East),
+                    Gamepad(GamepadButtonType::Select),
+                ],
+            ),
+            (
+                Action::ResetBinding,
+                vec![Key(KeyCode::Back), Gamepad(GamepadButtonType::West)],
+            ),
+            (
+                Action::Place,
+                vec![
+                    Mouse(MouseButton::Left),
+                    Gamepad(GamepadButtonType::RightTrigger2),
+                ],
+            ),
+            (
+                Action::Remove,
+                vec![
+                    Mouse(MouseButton::Right),
+                    Gamepad(GamepadButtonType::LeftTrigger2),
+                ],
+            ),
+            (
+                Action::NextTool,
+                vec![Key(KeyCode::Tab), Gamepad(GamepadButtonType::RightTrigger)],
+            ),
+            (
+                Action::ToggleBullets,
+                vec![Key(KeyCode::R), Gamepad(GamepadButtonType::North)],
+            ),
+            (
+                Action::RotateRect,
+                vec![Key(KeyCode::T), Gamepad(GamepadButtonType::LeftTrigger)],
+            ),
+            (
+                Action::SaveArena,
+                vec![Key(KeyCode::F5), Gamepad(GamepadButtonType::RightThumb)],
+            ),
+            (
+                Action::LoadArena,
+                vec![Key(KeyCode::F9), Gamepad(GamepadButtonType::LeftThumb)],
+            ),
+        ]))
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every binding of the action, separated by slashes.
+    pub fn labels(&self, action: Action) -> String {
+        let labels = self
+            .get(action)
+            .iter()
+            .map(Binding::label)
+            .collect::<Vec<_>>();
+        if labels.is_empty() {
+            return "unbound".to_string();
+        }
+        labels.join("/")
+    }
+
+    /// Every [`Action::MENU`] has a binding none of the others has, both on the keyboard and mouse
+    /// and on the gamepad, so the menus can always be used, the controls menu included.
+    pub fn menus_usable(&self) -> bool {
+        Action::MENU.iter().all(|action| {
+            [false, true].into_iter().all(|gamepad| {
+                self.get(*action).iter().any(|binding| {
+                    matches!(binding, Binding::Gamepad(_)) == gamepad
+                        && Action::MENU
+                            .iter()
+                            .all(|other| other == action || !self.get(*other).contains(binding))
+                })
+            })
+        })
+    }
+
+    /// Sets the bindings of the action, unless the menus can't be used anymore.
+    /// Returns whether it did.
+    fn set(&mut self, action: Action, bindings: Vec<Binding>) -> bool {
+        let previous = self.0.insert(action, bindings);
+        if self.menus_usable() {
+            return true;
+        }
+        match previous {
+            Some(previous) => self.0.insert(action, previous),
+            None => self.0.remove(&action),
+        };
+        false
+    }
+
+    /// Resets the action to its default bindings, see [`Bindings::rebind`].
+    pub fn reset(&mut self, action: Action) -> bool {
+        self.set(action, Self::default().get(action).to_vec())
+    }
+
+    /// Replaces the bindings of the action on the device of `binding`.
+    /// Refused when it leaves the menus unusable, see [`Bindings::menus_usable`], returns whether it
+    /// was done.
+    pub fn rebind(&mut self, action: Action, binding: Binding) -> bool {
+        let mut bindings = self.get(action).to_vec();
+        bindings.retain(|existing| !existing.same_device(&binding));
+        bindings.push(binding);
+        self.set(action, bindings)
+    }
+
+    /// Falls back to the defaults when nothing is saved or it can't be read,
+    /// and for the actions added since the bindings were saved.
+    pub fn load() -> Self {
+        match storage::read(STORAGE_NAME) {
+            Some(saved) => Self::from_ron(&saved),
+            None => Self::default(),
+        }
+    }
+
+    /// Parses saved bindings, like [`Bindings::load`].
+    /// The menu actions go back to their defaults when they made the menus unusable.
+    pub fn from_ron(saved: &str) -> Self {
+        let mut bindings = ron::from_str::<Self>(saved).unwrap_or_else(|error| {
+            warn!("Couldn't read the bindings: {error}");
+            Self::default()
+        });
+        let defaults = Self::default();
+        for action in Action::ALL {
+            bindings
+                .0
+                .entry(action)
+                .or_insert_with(|| defaults.get(action).to_vec());
+        }
+        if !bindings.menus_usable() {
+            warn!("The saved bindings left the menus unusable, they are reset");
+            for action in Action::MENU {
+                bindings.0.insert(action, defaults.get(action).to_vec());
+            }
+        }
+        bindings
+    }
+
+    pub fn save(&self) {
+        match ron::ser::to_string_pretty(self, default()) {
+            Ok(serialized) => storage::write(STORAGE_NAME, &serialized),
+            Err(error) => warn!("Couldn't serialize the bindings: {error}"),
+        }
+    }
+}
+
+/// Actions held and just pressed this frame, updated from the [`Bindings`] before `Update`.
+#[derive(Resource, Debug, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    /// Held through a gamepad button, to aim with the stick rather than the mouse.
+    pressed_on_gamepad: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn pressed_on_gamepad(&self, action: Action) -> bool {
+        self.pressed_on_gamepad.contains(&action)
+    }
+
+    /// Stops other systems from seeing the action as just pressed this frame.
+    pub fn consume(&mut self, action: Action) {
+        self.just_pressed.remove(&action);
+    }
+
+    /// Sum of the directions of the held actions, normalized.
+    pub fn direction(&self, up: Action, down: Action, left: Action, right: Action) -> Vec2 {
+        let mut direction = Vec2::ZERO;
+        for (action, towards) in [
+            (up, Vec2::Y),
+            (down, Vec2::NEG_Y),
+            (left, Vec2::NEG_X),
+            (right, Vec2::X),
+        ] {
+            if self.pressed(action) {
+                direction += towards;
+            }
+        }
+        direction.normalize_or_zero()
+    }
+}
+
+/// Devices controlling a local player.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlayerDevices {
+    pub keyboard_mouse: bool,
+    pub gamepad: Option<Gamepad>,
+}
+
+/// Devices and actions of each local player, by [`crate::player::Player`] index.
+///
+/// The keyboard and mouse always control the first player. In co-op the next players get a gamepad each,
+/// by order of connection, and the first player gets the one left over if any.
+#[derive(Resource, Debug, Default)]
+pub struct LocalPlayers {
+    pub devices: Vec<PlayerDevices>,
+    pub actions: Vec<ActionState>,
+}
+
+impl LocalPlayers {
+    pub fn get(&self, player: usize) -> Option<(&PlayerDevices, &ActionState)> {
+        self.devices.get(player).zip(self.actions.get(player))
+    }
+}
+
+/// Online, the devices all go to the local player, the other one is played by the other peer.
+fn assign_devices(
+    mode: GameMode,
+    online_player: Option<usize>,
+    active_gamepad: Option<Gamepad>,
+    gamepads: &Gamepads,
+) -> Vec<PlayerDevices> {
+    if let Some(online_player) = online_player {
+        let mut devices = vec![PlayerDevices::default(); mode.players()];
+        devices[online_player] = PlayerDevices {
+            keyboard_mouse: true,
+            gamepad: active_gamepad,
+        };
+        return devices;
+    }
+    if mode == GameMode::Solo {
+        return vec![PlayerDevices {
+            keyboard_mouse: true,
+            gamepad: active_gamepad,
+        }];
+    }
+    let mut gamepads = gamepads.iter().collect::<Vec<_>>();
+    gamepads.sort_by_key(|gamepad| gamepad.id);
+    let mut gamepads = gamepads.into_iter();
+    let mut devices = vec![PlayerDevices {
+        keyboard_mouse: true,
+        gamepad: None,
+    }];
+    devices.extend((1..mode.players()).map(|_| PlayerDevices {
+        keyboard_mouse: false,
+        gamepad: gamepads.next(),
+    }));
+    devices[0].gamepad = gamepads.next();
+    devices
+}
+
+impl ActionState {
+    fn update(
+        &mut self,
+        bindings: &Bindings,
+        devices: PlayerDevices,
+        keys: &Input<KeyCode>,
+        mouse_buttons: &Input<MouseButton>,
+        gamepad_buttons: &Input<GamepadButton>,
+    ) {
+        self.pressed.clear();
+        self.just_pressed.clear();
+        self.pressed_on_gamepad.clear();
+        for (action, bindings) in bindings.0.iter() {
+            for binding in bindings {
+                let (pressed, just_pressed) = match *binding {
+                    Binding::Key(key) if devices.keyboard_mouse => {
+                        (keys.pressed(key), keys.just_pressed(key))
+                    }
+                    Binding::Mouse(button) if devices.keyboard_mouse => (
+                        mouse_buttons.pressed(button),
+                        mouse_buttons.just_pressed(button),
+                    ),
+                    Binding::Gamepad(button_type) => match devices.gamepad {
+                        Some(gamepad) => {
+                            let button = GamepadButton::new(gamepad, button_type);
+                            (
+                                gamepad_buttons.pressed(button),
+                                gamepad_buttons.just_pressed(button),
+                            )
+                        }
+                        None => (false, false),
+                    },
+                    Binding::Key(_) | Binding::Mouse(_) => (false, false),
+                };
+                if pressed {
+                    self.pressed.insert(*action);
+                    if matches!(binding, Binding::Gamepad(_)) {
+                        self.pressed_on_gamepad.insert(*action);
+                    }
+                }
+                if just_pressed {
+                    self.just_pressed.insert(*action);
+                }
+            }
+        }
+    }
+}
+
+/// The [`ActionState`] of the menus takes the keyboard, the mouse and the [`ActiveGamepad`],
+/// each [`LocalPlayers`] only their own devices.
+fn update_action_state(
+    bindings: Res<Bindings>,
+    mode: Res<GameMode>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    active_gamepad: Res<ActiveGamepad>,
+    session: Option<Res<OnlineSession>>,
+    mut action_state: ResMut<ActionState>,
+    mut local_players: ResMut<LocalPlayers>,
+) {
+    let menu_devices = PlayerDevices {
+        keyboard_mouse: true,
+        gamepad: active_gamepad.0,
+    };
+    action_state.update(
+        &bindings,
+        menu_devices,
+        &keys,
+        &mouse_buttons,
+        &gamepad_buttons,
+    );
+    let local_players = &mut *local_players;
+    local_players.devices = assign_devices(
+        *mode,
+        session.map(|session| session.local_player()),
+        active_gamepad.0,
+        &gamepads,
+    );
+    local_players
+        .actions
+        .resize_with(local_players.devices.len(), ActionState::default);
+    for (actions, devices) in local_players.actions.iter_mut().zip(&local_players.devices) {
+        actions.update(&bindings, *devices, &keys, &mouse_buttons, &gamepad_buttons);
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::{
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+};
+use bevy_asset_loader::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    behaviour::Behaviour, boss::BossPhase, bullets::Weapon, menu::GameState,
+    ron_asset::RonAssetLoader, steering::Steering,
+};
+
+/// Loads every [`EnemyArchetype`] listed in `assets/enemies.assets.ron` before the game starts.
+pub struct EnemyArchetypesPlugin;
+
+impl Plugin for EnemyArchetypesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<EnemyArchetype>()
+            .add_asset_loader(RonAssetLoader::<EnemyArchetype>::new(&["enemy.ron"]))
+            .add_loading_state(
+                LoadingState::new(GameState::Loading).continue_to_state(GameState::MainMenu),
+            )
+            .add_dynamic_collection_to_loading_state::<_, StandardDynamicAssetCollection>(
+                GameState::Loading,
+                "enemies.assets.ron",
+            )
+            .add_collection_to_loading_state::<_, EnemyAssets>(GameState::Loading);
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct EnemyAssets {
+    #[asset(key = "enemies", collection(typed))]
+    pub archetypes: Vec<Handle<EnemyArchetype>>,
+}
+
+impl EnemyAssets {
+    pub fn get<'a>(
+        &self,
+        name: &str,
+        archetypes: &'a Assets<EnemyArchetype>,
+    ) -> Option<&'a EnemyArchetype> {
+        self.archetypes
+            .iter()
+            .filter_map(|handle| archetypes.get(handle))
+            .find(|archetype| archetype.name == name)
+    }
+}
+
+/// An enemy type, declared in a `.enemy.ron` file under `assets/enemies`.
+#[derive(Deserialize, TypeUuid, TypePath, Debug, Clone)]
+#[uuid = "48a10235-35df-40b0-9d51-2d78f80d3fd7"]
+pub struct EnemyArchetype {
+    pub name: String,
+    pub speed: f32,
+    pub health: f32,
+    /// Points for killing it, see [`crate::stats::RunStats`].
+    pub score: u32,
+    pub weapon: Weapon,
+    pub color: Color,
+    pub radius: f32,
+    /// Radius used for collisions, usually larger than the drawn one to be forgiving.
+    pub collider_radius: f32,
+    pub drops: Vec<Drop>,
+    #[serde(default)]
+    pub steering: Steering,
+    #[serde(default)]
+    pub behaviour: Behaviour,
+    /// Makes it a [`crate::boss::Boss`], its weapon is only used for the speed, damage and lifetime of the bullets.
+    #[serde(default)]
+    pub phases: Vec<BossPhase>,
+}
+
+/// What an enemy may leave behind when it dies.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Drop {
+    /// Between 0 and 1.
+    pub chance: f32,
+    pub pickup: Pickup,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum Pickup {
+    Health(f32),
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct DropTable(pub Vec<Drop>);
+
+/// How an entity is drawn, overriding its team color.
+#[derive(Component, Debug, Clone)]
+pub struct Appearance {
+    pub color: Color,
+    pub radius: f32,
+}
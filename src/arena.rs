@@ -0,0 +1,283 @@
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+};
+use bevy_asset_loader::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena_generator::add_generated_arena, menu::GameState, ron_asset::RonAssetLoader,
+    simulation_time::SimulationTime, spawn_pickup, HealthPickup,
+};
+
+/// Loads every [`Arena`] listed in `assets/arenas.assets.ron` before the game starts,
+/// followed by the one generated from the seed, see [`crate::arena_generator`].
+pub struct ArenaPlugin;
+
+impl Plugin for ArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Arena>()
+            .add_asset_loader(RonAssetLoader::<Arena>::new(&["arena.ron"]))
+            .add_dynamic_collection_to_loading_state::<_, StandardDynamicAssetCollection>(
+                GameState::Loading,
+                "arenas.assets.ron",
+            )
+            .add_collection_to_loading_state::<_, ArenaAssets>(GameState::Loading)
+            .add_systems(OnExit(GameState::Loading), add_generated_arena)
+            .init_resource::<Arena>()
+            .init_resource::<SelectedArena>();
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct ArenaAssets {
+    #[asset(key = "arenas", collection(typed))]
+    pub arenas: Vec<Handle<Arena>>,
+}
+
+impl ArenaAssets {
+    /// The arena named `name`, or the first one when there is none.
+    pub fn get<'a>(&self, name: &str, arenas: &'a Assets<Arena>) -> Option<&'a Arena> {
+        let mut loaded = self.arenas.iter().filter_map(|handle| arenas.get(handle));
+        let first = loaded.clone().next();
+        loaded.find(|arena| arena.name == name).or(first)
+    }
+
+    /// Names of the arenas, in the order of `assets/arenas.assets.ron`.
+    pub fn names(&self, arenas: &Assets<Arena>) -> Vec<String> {
+        self.arenas
+            .iter()
+            .filter_map(|handle| arenas.get(handle))
+            .map(|arena| arena.name.clone())
+            .collect()
+    }
+}
+
+/// Name of the [`Arena`] the next runs are played in, picked from the main menu.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SelectedArena(pub String);
+
+/// What happens to a bullet hitting an [`Obstacle`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BulletResponse {
+    /// The bullet is destroyed, unless it still has bounces left.
+    #[default]
+    Absorb,
+    /// The bullet bounces off, without using its bounces.
+    Reflect,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Circle { center: Vec2, radius: f32 },
+    Rect { center: Vec2, half_size: Vec2 },
+}
+
+impl Shape {
+    /// Bottom left and top right corners of the box around the shape.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match *self {
+            Shape::Circle { center, radius } => (center - radius, center + radius),
+            Shape::Rect { center, half_size } => (center - half_size, center + half_size),
+        }
+    }
+
+    /// Where a circle at `position` must go to stop overlapping the shape, and the normal of the
+    /// surface it touches. `None` when it doesn't overlap.
+    pub fn push_out(&self, position: Vec2, radius: f32) -> Option<(Vec2, Vec2)> {
+        match *self {
+            Shape::Circle {
+                center,
+                radius: shape_radius,
+            } => {
+                let away = position - center;
+                let distance = away.length();
+                if distance >= shape_radius + radius {
+                    return None;
+                }
+                let normal = away.try_normalize().unwrap_or(Vec2::X);
+                Some((center + normal * (shape_radius + radius), normal))
+            }
+            Shape::Rect { center, half_size } => {
+                let closest = position.clamp(center - half_size, center + half_size);
+                let away = position - closest;
+                if away != Vec2::ZERO {
+                    let distance = away.length();
+                    if distance >= radius {
+                        return None;
+                    }
+                    let normal = away / distance;
+                    return Some((closest + normal * radius, normal));
+                }
+                // Inside, leaves through the closest side.
+                let local = position - center;
+                let depth = half_size - local.abs();
+                let normal = match depth.x < depth.y {
+                    true => Vec2::new(local.x.signum(), 0f32),
+                    false => Vec2::new(0f32, local.y.signum()),
+                };
+                let outside = center + normal * (half_size + radius);
+                let pushed = match depth.x < depth.y {
+                    true => Vec2::new(outside.x, position.y),
+                    false => Vec2::new(position.x, outside.y),
+                };
+                Some((pushed, normal))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Obstacle {
+    pub shape: Shape,
+    #[serde(default)]
+    pub bullets: BulletResponse,
+}
+
+/// Heals whoever picks it up, every `interval` seconds unless the previous one is still there.
+#[derive(Component, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PickupSpawner {
+    pub position: Vec2,
+    pub amount: f32,
+    pub interval: f32,
+    /// Seconds before the next pickup, the first one comes after a full interval.
+    #[serde(skip)]
+    pub next_in: f32,
+}
+
+/// Where a run is played, declared in a `.arena.ron` file under `assets/arenas`.
+///
+/// Its walls, centered on the origin, and its obstacles block the movement of every entity with
+/// a [`crate::spatial_hash::Collider`], bullets are stopped or bounce instead,
+/// see [`crate::bullets::bullets_walls`]. The resource is the arena of the current run.
+#[derive(Resource, Serialize, Deserialize, TypeUuid, TypePath, Debug, Clone, PartialEq)]
+#[uuid = "0f4c5a0e-7e43-4d2b-9a57-3c1f0f2e8b61"]
+pub struct Arena {
+    pub name: String,
+    /// Enemies spawn on the edge of this rectangle, unless it has `enemy_spawns`.
+    pub half_size: Vec2,
+    /// Image stretched over the whole arena.
+    #[serde(default)]
+    pub background: Option<String>,
+    /// Looped while playing in the arena.
+    #[serde(default)]
+    pub music: Option<String>,
+    /// Where each player starts, by index, the ones without a spawn of their own start at the center.
+    #[serde(default)]
+    pub player_spawns: Vec<Vec2>,
+    /// Where the enemies of [`crate::waves::SpawnLocation::SpawnPoint`] come from.
+    #[serde(default)]
+    pub enemy_spawns: Vec<Vec2>,
+    #[serde(default)]
+    pub pickup_spawners: Vec<PickupSpawner>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+}
+
+/// An open arena, used until the arena files are loaded.
+impl Default for Arena {
+    fn default() -> Self {
+        Self {
+            name: "Open".to_string(),
+            half_size: Vec2::new(768f32, 512f32),
+            background: Some("bg.jpg".to_string()),
+            music: None,
+            player_spawns: vec![
+                Vec2::ZERO,
+                Vec2::new(-48f32, 0f32),
+                Vec2::new(48f32, 0f32),
+                Vec2::new(0f32, -48f32),
+            ],
+            enemy_spawns: Vec::new(),
+            pickup_spawners: Vec::new(),
+            obstacles: Vec::new(),
+        }
+    }
+}
+
+impl Arena {
+    pub fn player_spawn(&self, index: usize) -> Vec2 {
+        self.player_spawns.get(index).copied().unwrap_or(Vec2::ZERO)
+    }
+
+    /// One of the `enemy_spawns` at random, or a random point of the edge when there is none.
+    pub fn enemy_spawn(&self, rng: &mut impl Rng) -> Vec2 {
+        match self.enemy_spawns.choose(rng) {
+            Some(spawn) => *spawn,
+            None => self.edge_point(rng.gen_range(0f32..std::f32::consts::TAU)),
+        }
+    }
+
+    /// The point of the edge in that direction, in radians.
+    pub fn edge_point(&self, angle: f32) -> Vec2 {
+        let direction = Vec2::from_angle(angle);
+        let scale =
+            (self.half_size.x / direction.x.abs()).min(self.half_size.y / direction.y.abs());
+        direction * scale
+    }
+
+    /// Where a circle at `position` must go to stay inside the walls and out of the obstacles.
+    pub fn constrain(&self, mut position: Vec2, radius: f32) -> Vec2 {
+        for obstacle in self.obstacles.iter() {
+            if let Some((pushed, _)) = obstacle.shape.push_out(position, radius) {
+                position = pushed;
+            }
+        }
+        let inner = (self.half_size - radius).max(Vec2::ZERO);
+        position.clamp(-inner, inner)
+    }
+
+    /// The first wall or obstacle a circle at `position` overlaps, with where the circle must go
+    /// to stop overlapping it and the normal of the surface it touches.
+    pub fn hit(&self, position: Vec2, radius: f32) -> Option<(BulletResponse, Vec2, Vec2)> {
+        let inner = (self.half_size - radius).max(Vec2::ZERO);
+        if position.abs().cmpgt(inner).any() {
+            let normal = Vec2::new(
+                if position.x.abs() > inner.x {
+                    -position.x.signum()
+                } else {
+                    0f32
+                },
+                if position.y.abs() > inner.y {
+                    -position.y.signum()
+                } else {
+                    0f32
+                },
+            )
+            .normalize();
+            return Some((
+                BulletResponse::Absorb,
+                position.clamp(-inner, inner),
+                normal,
+            ));
+        }
+        self.obstacles.iter().find_map(|obstacle| {
+            let (pushed, normal) = obstacle.shape.push_out(position, radius)?;
+            Some((obstacle.bullets, pushed, normal))
+        })
+    }
+}
+
+/// Spawns the pickups of the [`PickupSpawner`]s of the arena, spawned with each run.
+pub fn run_pickup_spawners(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut q_spawners: Query<&mut PickupSpawner>,
+    q_pickups: Query<&Transform, With<HealthPickup>>,
+) {
+    for mut spawner in q_spawners.iter_mut() {
+        spawner.next_in -= time.delta_seconds();
+        if spawner.next_in > 0f32 {
+            continue;
+        }
+        spawner.next_in += spawner.interval;
+        let taken = q_pickups
+            .iter()
+            .any(|transform| transform.translation.xy().distance(spawner.position) < 1f32);
+        if !taken {
+            spawn_pickup(&mut commands, spawner.amount, spawner.position);
+        }
+    }
+}
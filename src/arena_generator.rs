@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    arena::{Arena, ArenaAssets, BulletResponse, Obstacle, PickupSpawner, Shape},
+    nav_grid::{NavGrid, CELL_SIZE},
+    rng::GameRng,
+};
+
+/// Name of the arena generated from the seed of the game.
+pub const GENERATED: &str = "Generated";
+/// Radius of the smallest and largest colliders, players and the biggest boss,
+/// both must be able to go anywhere they fit.
+pub const CLEARANCES: [f32; 2] = [18f32, 36f32];
+/// No obstacle comes closer than this to the center, where the players start.
+pub const START_CLEARANCE: f32 = 160f32;
+/// Enemies spawn at least this far from the center.
+pub const MIN_SPAWN_DISTANCE: f32 = 400f32;
+
+/// Adds the arena generated from the seed of the game to the loaded ones, so it can be picked
+/// from the menu like the others.
+pub fn add_generated_arena(
+    rng: Res<GameRng>,
+    mut arena_assets: ResMut<ArenaAssets>,
+    mut arenas: ResMut<Assets<Arena>>,
+) {
+    arena_assets
+        .arenas
+        .push(arenas.add(generate_arena(rng.seed())));
+}
+
+/// Replaces the generated arena with the one of `seed`, after the seed of the game changed.
+pub fn regenerate_arena(seed: u64, arena_assets: &ArenaAssets, arenas: &mut Assets<Arena>) {
+    let generated = arena_assets
+        .arenas
+        .iter()
+        .find(|handle| {
+            arenas
+                .get(*handle)
+                .is_some_and(|arena| arena.name == GENERATED)
+        })
+        .cloned();
+    if let Some(arena) = generated.and_then(|handle| arenas.get_mut(&handle)) {
+        *arena = generate_arena(seed);
+    }
+}
+
+/// An arena with random obstacles, the same for the same seed.
+///
+/// Obstacles are only kept when every place a player or the biggest boss fits in can still be
+/// reached from the center, so nothing gets trapped in an enclosed pocket.
+pub fn generate_arena(seed: u64) -> Arena {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut arena = Arena {
+        name: GENERATED.to_string(),
+        half_size: Vec2::new(
+            rng.gen_range(640f32..=900f32),
+            rng.gen_range(420f32..=600f32),
+        ),
+        enemy_spawns: Vec::new(),
+        pickup_spawners: Vec::new(),
+        obstacles: Vec::new(),
+        ..default()
+    };
+    let mut grids = CLEARANCES.map(|clearance| NavGrid::new(&arena, CELL_SIZE, clearance));
+    let starts = [&grids[0], &grids[1]].map(|grid| grid.cell(Vec2::ZERO).unwrap());
+
+    let count = rng.gen_range(6..=14);
+    for _ in 0..count * 4 {
+        if arena.obstacles.len() == count {
+            break;
+        }
+        let obstacle = random_obstacle(&mut rng, arena.half_size);
+        if obstacle
+            .shape
+            .push_out(Vec2::ZERO, START_CLEARANCE)
+            .is_some()
+        {
+            continue;
+        }
+        let mut blocked = grids.clone();
+        let connected = blocked.iter_mut().zip(starts).all(|(grid, start)| {
+            grid.block(&obstacle);
+            grid.is_connected(start)
+        });
+        if connected {
+            grids = blocked;
+            arena.obstacles.push(obstacle);
+        }
+    }
+
+    let [small, large] = &grids;
+    let spawn_cells: Vec<UVec2> = large
+        .cells()
+        .filter(|cell| large.is_free(*cell))
+        .filter(|cell| large.center(*cell).length() >= MIN_SPAWN_DISTANCE)
+        .collect();
+    // Without replacement, so the spawns are all distinct.
+    let spawn_count = rng.gen_range(4..=8);
+    arena.enemy_spawns = spawn_cells
+        .choose_multiple(&mut rng, spawn_count)
+        .map(|cell| large.center(*cell))
+        .collect();
+    let pickup_cells: Vec<UVec2> = small
+        .cells()
+        .filter(|cell| small.is_free(*cell))
+        .filter(|cell| small.center(*cell).length() >= START_CLEARANCE)
+        .collect();
+    let pickup_count = rng.gen_range(1..=3);
+    arena.pickup_spawners = pickup_cells
+        .choose_multiple(&mut rng, pickup_count)
+        .map(|cell| PickupSpawner {
+            position: small.center(*cell),
+            amount: 0.25f32,
+            interval: 30f32,
+            next_in: 0f32,
+        })
+        .collect();
+    arena
+}
+
+fn random_obstacle(rng: &mut impl Rng, half_size: Vec2) -> Obstacle {
+    let center = Vec2::new(
+        rng.gen_range(-half_size.x..=half_size.x),
+        rng.gen_range(-half_size.y..=half_size.y),
+    );
+    let shape = match rng.gen_bool(0.4f64) {
+        true => Shape::Circle {
+            center,
+            radius: rng.gen_range(24f32..=80f32),
+        },
+        false => {
+            let half_size = Vec2::new(rng.gen_range(40f32..=200f32), 16f32);
+            Shape::Rect {
+                center,
+                half_size: match rng.gen_bool(0.5f64) {
+                    true => half_size,
+                    false => Vec2::new(half_size.y, half_size.x),
+                },
+            }
+        }
+    };
+    Obstacle {
+        shape,
+        bullets: match rng.gen_bool(0.3f64) {
+            true => BulletResponse::Reflect,
+            false => BulletResponse::Absorb,
+        },
+    }
+}
@@ -0,0 +1,284 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    ai::{nearest_player, nearest_player_index, Ai},
+    boss::Boss,
+    bullets::{CommandsSpawnBullet, Weapon},
+    movement::{MoveDirection, MoveSpeed},
+    player::Player,
+    rng::GameRng,
+    simulation_time::SimulationTime,
+    steering::Steering,
+    utils::intercept,
+    Cooldown, Health, TeamIdx,
+};
+
+/// What an AI is trying to do, the [`Steering`] turns it into a velocity.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tactic {
+    /// Goes straight for the nearest player, until `flee_radius`.
+    Approach,
+    /// Circles the nearest player at `orbit_radius`.
+    #[default]
+    KeepDistance,
+    /// Circles the nearest player wherever it is, changing direction each time it starts.
+    Strafe,
+    /// Runs away from the nearest player, without firing.
+    Retreat,
+}
+
+/// Scores how much a [`Tactic`] fits the situation, between 0 and 1.
+///
+/// Each one goes linearly from 0 at `from` to 1 at `to`, `from` can be above `to` to score low values higher.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum Consideration {
+    /// Distance to the nearest player.
+    Distance { from: f32, to: f32 },
+    /// Fraction of the [`Health`] left.
+    Health { from: f32, to: f32 },
+}
+
+impl Consideration {
+    fn score(&self, distance: f32, health: f32) -> f32 {
+        let (value, from, to) = match *self {
+            Consideration::Distance { from, to } => (distance, from, to),
+            Consideration::Health { from, to } => (health, from, to),
+        };
+        if from == to {
+            return if value >= to { 1f32 } else { 0f32 };
+        }
+        ((value - from) / (to - from)).clamp(0f32, 1f32)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UtilityOption {
+    pub tactic: Tactic,
+    #[serde(default = "one")]
+    pub weight: f32,
+    /// Multiplied together with the weight, none always scores the weight.
+    #[serde(default)]
+    pub considerations: Vec<Consideration>,
+}
+
+fn one() -> f32 {
+    1f32
+}
+
+/// Shots fired in a row, as fast as the [`Weapon`] allows, then a pause.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Burst {
+    /// At least 1, a burst of 0 shots fires one.
+    pub shots: u32,
+    /// Seconds between the last shot of a burst and the first of the next one.
+    pub pause: f32,
+    /// Only starts a burst when the nearest player is this close.
+    pub range: f32,
+    /// Shoots where the player will be if they keep moving the same way, rather than where they are.
+    #[serde(default)]
+    pub lead: bool,
+    /// Between 0 and 1, each shot deviates at random by up to [`MAX_AIM_ERROR`] at 0, not at all at 1.
+    #[serde(default = "one")]
+    pub accuracy: f32,
+}
+
+/// Radians a shot deviates by at most, with an accuracy of 0.
+pub const MAX_AIM_ERROR: f32 = 0.5f32;
+
+impl Default for Burst {
+    fn default() -> Self {
+        Self {
+            shots: 1,
+            pause: 3f32,
+            range: 500f32,
+            lead: false,
+            accuracy: 1f32,
+        }
+    }
+}
+
+/// Decisions of an AI, declared by its [`crate::archetypes::EnemyArchetype`].
+///
+/// Every `think_interval` seconds, the [`Tactic`] of the best scoring option is picked.
+#[derive(Component, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Behaviour {
+    pub options: Vec<UtilityOption>,
+    pub think_interval: f32,
+    /// Added to the score of the current tactic, so close scores don't flip back and forth.
+    pub commitment: f32,
+    pub burst: Burst,
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self {
+            options: vec![UtilityOption {
+                tactic: Tactic::KeepDistance,
+                weight: 1f32,
+                considerations: Vec::new(),
+            }],
+            think_interval: 0.5f32,
+            commitment: 0.1f32,
+            burst: Burst::default(),
+        }
+    }
+}
+
+impl Behaviour {
+    /// Best scoring tactic, the first one on ties.
+    pub fn decide(&self, current: Tactic, distance: f32, health: f32) -> Tactic {
+        let mut best = (current, f32::MIN);
+        for option in self.options.iter() {
+            let mut score = option.weight
+                * option
+                    .considerations
+                    .iter()
+                    .map(|consideration| consideration.score(distance, health))
+                    .product::<f32>();
+            if option.tactic == current {
+                score += self.commitment;
+            }
+            if score > best.1 {
+                best = (option.tactic, score);
+            }
+        }
+        best.0
+    }
+}
+
+/// Where an AI is in its [`Behaviour`].
+#[derive(Component, Debug, Clone, Default)]
+pub struct Brain {
+    pub tactic: Tactic,
+    /// Seconds before deciding again.
+    pub think_in: f32,
+    /// Seconds before the next burst may start.
+    pub burst_in: f32,
+    /// Shots left in the current burst.
+    pub shots_left: u32,
+}
+
+impl Brain {
+    /// Thinks and fires at random times at first, so the AIs spawned together don't act together.
+    pub fn new(behaviour: &Behaviour, rng: &mut impl Rng) -> Self {
+        Self {
+            tactic: Tactic::default(),
+            think_in: rng.gen_range(0f32..=behaviour.think_interval),
+            burst_in: rng.gen_range(0f32..=behaviour.burst.pause),
+            shots_left: 0,
+        }
+    }
+}
+
+pub fn ai_think(
+    time: Res<SimulationTime>,
+    q_players: Query<&Transform, With<Player>>,
+    mut q_ais: Query<(&Transform, &Health, &Behaviour, &mut Brain, &mut Steering), With<Ai>>,
+) {
+    let players = q_players
+        .iter()
+        .map(|transform| transform.translation.xy())
+        .collect::<Vec<_>>();
+    for (transform, health, behaviour, mut brain, mut steering) in q_ais.iter_mut() {
+        brain.think_in -= time.delta_seconds();
+        if brain.think_in > 0f32 {
+            continue;
+        }
+        brain.think_in += behaviour.think_interval;
+        let position = transform.translation.xy();
+        let Some(player) = nearest_player(position, &players) else {
+            continue;
+        };
+        let tactic = behaviour.decide(
+            brain.tactic,
+            position.distance(player),
+            health.current / health.max,
+        );
+        if tactic == Tactic::Strafe && brain.tactic != Tactic::Strafe {
+            steering.clockwise = !steering.clockwise;
+        }
+        brain.tactic = tactic;
+    }
+}
+
+/// Fires the [`Burst`] of each AI at the nearest player, unless it retreats.
+///
+/// Bosses fire their own patterns instead, see [`crate::boss`].
+pub fn ai_fire(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut rng: ResMut<GameRng>,
+    mut q_attackers: Query<
+        (
+            Entity,
+            &Transform,
+            &Weapon,
+            &TeamIdx,
+            &Cooldown,
+            &Behaviour,
+            &mut Brain,
+        ),
+        (With<Ai>, Without<Boss>),
+    >,
+    q_players: Query<(&Transform, &MoveDirection, &MoveSpeed), With<Player>>,
+) {
+    let players = q_players
+        .iter()
+        .map(|(transform, _, _)| transform.translation.xy())
+        .collect::<Vec<_>>();
+    let velocities = q_players
+        .iter()
+        .map(|(_, direction, speed)| direction.0 * speed.0)
+        .collect::<Vec<_>>();
+    for (entity, transform, weapon, team, cooldown, behaviour, mut brain) in q_attackers.iter_mut()
+    {
+        let position = transform.translation.xy();
+        let burst = &behaviour.burst;
+        brain.burst_in -= time.delta_seconds();
+        let Some(nearest) = nearest_player_index(position, &players) else {
+            continue;
+        };
+        let player = players[nearest];
+        if brain.shots_left == 0 {
+            if brain.burst_in > 0f32
+                || brain.tactic == Tactic::Retreat
+                || position.distance(player) > burst.range
+            {
+                continue;
+            }
+            brain.shots_left = burst.shots.max(1);
+        }
+        if !cooldown.is_ready(time.elapsed_seconds()) {
+            continue;
+        }
+        let aim = match burst.lead {
+            true => {
+                intercept(position, player, velocities[nearest], weapon.speed).unwrap_or(player)
+            }
+            false => player,
+        };
+        let error = rng.rng.gen_range(-1f32..=1f32)
+            * (1f32 - burst.accuracy.clamp(0f32, 1f32))
+            * MAX_AIM_ERROR;
+        let fired = commands
+            .spawn_bullet(
+                entity,
+                position,
+                Vec2::from_angle(error).rotate((aim - position).normalize_or_zero()),
+                team.clone(),
+                weapon,
+                cooldown,
+                &time,
+            )
+            .is_ok();
+        if fired {
+            brain.shots_left -= 1;
+            if brain.shots_left == 0 {
+                brain.burst_in = burst.pause;
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::f32::consts::TAU;
+
+use bevy::{math::Vec3Swizzles, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    ai::nearest_player,
+    bullets::{CommandsSpawnBullet, Weapon},
+    player::Player,
+    simulation_time::SimulationTime,
+    Cooldown, Health, TeamIdx,
+};
+
+/// Bullets fired by a boss during a [`BossPhase`], with the speed, damage and lifetime of its [`Weapon`].
+#[derive(Deserialize, Debug, Clone)]
+pub enum Pattern {
+    /// Projectiles all around, one of them aimed at the nearest player.
+    Ring { projectiles: u32, interval: f32 },
+    /// Projectiles all around, turning by `spin` radians per second.
+    Spiral { arms: u32, spin: f32, interval: f32 },
+    /// Shots at the nearest player, `shots` in a row, at least 1, then a pause.
+    AimedBursts {
+        shots: u32,
+        interval: f32,
+        pause: f32,
+        #[serde(default = "one")]
+        projectiles: u32,
+        #[serde(default)]
+        spread: f32,
+    },
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl Pattern {
+    /// The boss weapon, shaped by the pattern.
+    fn weapon(&self, base: &Weapon) -> Weapon {
+        let (projectile_count, spread, interval) = match *self {
+            Pattern::Ring {
+                projectiles,
+                interval,
+            } => (projectiles, TAU, interval),
+            Pattern::Spiral { arms, interval, .. } => (arms, TAU, interval),
+            Pattern::AimedBursts {
+                projectiles,
+                spread,
+                interval,
+                ..
+            } => (projectiles, spread, interval),
+        };
+        Weapon {
+            fire_rate: 1f32 / interval,
+            projectile_count,
+            spread,
+            ..base.clone()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BossPhase {
+    /// Starts once the fraction of health left goes below this, the first phase has 1.
+    pub below: f32,
+    pub pattern: Pattern,
+}
+
+/// An enemy switching between bullet patterns as it loses [`Health`], shown with a health bar.
+///
+/// Spawned from an [`crate::archetypes::EnemyArchetype`] with phases.
+#[derive(Component, Debug, Clone)]
+pub struct Boss {
+    pub name: String,
+    pub phases: Vec<BossPhase>,
+    pub phase: usize,
+    /// Where the spiral points at.
+    pub angle: f32,
+    /// Shots left in the current burst.
+    pub shots_left: u32,
+    /// Seconds before the next burst.
+    pub pause_left: f32,
+}
+
+impl Boss {
+    pub fn new(name: &str, phases: &[BossPhase]) -> Self {
+        Self {
+            name: name.to_string(),
+            phases: phases.to_vec(),
+            phase: 0,
+            angle: 0f32,
+            shots_left: 0,
+            pause_left: 0f32,
+        }
+    }
+
+    pub fn pattern(&self) -> Option<&Pattern> {
+        self.phases.get(self.phase).map(|phase| &phase.pattern)
+    }
+}
+
+/// Goes to the last phase whose threshold the health went below, phases never go back.
+pub fn boss_phases(mut q_bosses: Query<(&mut Boss, &Health)>) {
+    for (mut boss, health) in q_bosses.iter_mut() {
+        let fraction = health.current / health.max;
+        let Some(phase) = boss
+            .phases
+            .iter()
+            .rposition(|phase| fraction <= phase.below)
+        else {
+            continue;
+        };
+        if phase > boss.phase {
+            boss.phase = phase;
+            boss.shots_left = 0;
+            boss.pause_left = 0f32;
+        }
+    }
+}
+
+pub fn boss_fire(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut q_bosses: Query<(Entity, &Transform, &Weapon, &TeamIdx, &Cooldown, &mut Boss)>,
+    q_players: Query<&Transform, With<Player>>,
+) {
+    let players = q_players
+        .iter()
+        .map(|transform| transform.translation.xy())
+        .collect::<Vec<_>>();
+    let delta = time.delta_seconds();
+    for (entity, transform, base, team, cooldown, mut boss) in q_bosses.iter_mut() {
+        let Some(pattern) = boss.pattern().cloned() else {
+            continue;
+        };
+        let position = transform.translation.xy();
+        let Some(player) = nearest_player(position, &players) else {
+            continue;
+        };
+        let aimed = (player - position).normalize_or_zero();
+        let direction = match pattern {
+            Pattern::Ring { .. } => aimed,
+            Pattern::Spiral { spin, .. } => {
+                boss.angle = (boss.angle + spin * delta).rem_euclid(TAU);
+                Vec2::from_angle(boss.angle)
+            }
+            Pattern::AimedBursts { shots, .. } => {
+                boss.pause_left -= delta;
+                if boss.shots_left == 0 {
+                    if boss.pause_left > 0f32 {
+                        continue;
+                    }
+                    boss.shots_left = shots.max(1);
+                }
+                aimed
+            }
+        };
+        let fired = commands
+            .spawn_bullet(
+                entity,
+                position,
+                direction,
+                team.clone(),
+                &pattern.weapon(base),
+                cooldown,
+                &time,
+            )
+            .is_ok();
+        if let (true, Pattern::AimedBursts { pause, .. }) = (fired, &pattern) {
+            boss.shots_left -= 1;
+            if boss.shots_left == 0 {
+                boss.pause_left = *pause;
+            }
+        }
+    }
+}
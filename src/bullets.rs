@@ -1,17 +1,26 @@
+use std::f32::consts::TAU;
+
 use bevy::{ecs::system::Command, math::Vec3Swizzles, prelude::*};
 use bevy_asset_loader::prelude::*;
+use serde::Deserialize;
 
 use crate::{
+    ai::nearest_player,
+    arena::{Arena, BulletResponse},
     despawn_after::DespawnAfter,
     menu::GameState,
     movement::{MoveDirection, MoveSpeed},
     player::Player,
-    Cooldown, RemoveOnRespawn, TeamIdx,
+    rollback::PresentedTick,
+    simulation_time::SimulationTime,
+    spatial_hash::Collider,
+    Cooldown, GameDef, Health, RemoveOnRespawn, TeamIdx,
 };
 
-pub struct BulletPlugin;
+/// Plays a sound for each bullet spawned, presentation only.
+pub struct BulletAudioPlugin;
 
-impl Plugin for BulletPlugin {
+impl Plugin for BulletAudioPlugin {
     fn build(&self, app: &mut App) {
         app.init_collection::<BulletAssets>();
         app.add_systems(
@@ -27,9 +36,83 @@ struct BulletAssets {
     pew1: Handle<AudioSource>,
 }
 
-#[derive(Event, Debug)]
+#[derive(Event, Debug, Clone)]
 pub struct EventBulletSpawn {
+    pub shooter: Entity,
     pub origin: Vec2,
+    /// Projectiles fired at once.
+    pub projectiles: u32,
+    /// Tick the bullets were fired in, see [`PresentedTick`].
+    pub tick: u64,
+}
+
+/// How an entity shoots, used for both the [`Player`] and the AIs.
+#[derive(Component, Deserialize, Debug, Clone)]
+pub struct Weapon {
+    /// Shots per second.
+    pub fire_rate: f32,
+    /// Projectiles fired at once by a shot.
+    #[serde(default = "one")]
+    pub projectile_count: u32,
+    /// Radians covered by the projectiles of a shot, a full turn or more makes a ring.
+    #[serde(default)]
+    pub spread: f32,
+    pub speed: f32,
+    pub damage: f32,
+    /// Seconds before a projectile despawns.
+    pub lifetime: f32,
+    #[serde(default)]
+    pub behaviour: BulletBehaviour,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl Weapon {
+    pub fn cooldown(&self) -> f32 {
+        1f32 / self.fire_rate
+    }
+
+    /// Directions of the projectiles of a shot aimed at `aim`.
+    pub fn directions(&self, aim: Vec2) -> Vec<Vec2> {
+        let count = self.projectile_count.max(1);
+        let (start, step) = if self.spread >= TAU {
+            (0f32, TAU / count as f32)
+        } else if count > 1 {
+            (-self.spread / 2f32, self.spread / (count - 1) as f32)
+        } else {
+            (0f32, 0f32)
+        };
+        (0..count)
+            .map(|i| Vec2::from_angle(start + step * i as f32).rotate(aim))
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub enum BulletBehaviour {
+    #[default]
+    Straight,
+    /// Turns toward the closest enemy, at most `turn_rate` radians per second.
+    Homing { turn_rate: f32 },
+    /// Goes through up to `hits` entities.
+    Piercing { hits: u32 },
+    /// Bounces on the arena walls and obstacles up to `bounces` times, instead of being stopped.
+    Bouncing { bounces: u32 },
+    /// Damages every entity within `radius` on impact.
+    Exploding { radius: f32 },
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Bullet {
+    pub damage: f32,
+    /// Keeps track of the remaining bounces.
+    pub behaviour: BulletBehaviour,
+    /// Entities already hit, so a piercing bullet doesn't hit them twice.
+    pub hits: Vec<Entity>,
+    /// Already sent damages, so it counts once in [`crate::stats::RunStats::shots_hit`].
+    pub has_hit: bool,
 }
 
 pub struct SpawnBulletCommand {
@@ -37,46 +120,64 @@ pub struct SpawnBulletCommand {
     team: TeamIdx,
     from_position: Vec2,
     to_direction: Vec2,
+    weapon: Weapon,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct BulletOwner {
     pub entity: Entity,
 }
 
 impl Command for SpawnBulletCommand {
     fn apply(self, world: &mut World) {
-        world.spawn((
-            Transform {
-                translation: self.from_position.extend(2f32),
-                ..default()
-            },
-            MoveSpeed(400f32),
-            MoveDirection(self.to_direction),
-            DespawnAfter {
-                timer: Timer::from_seconds(2000., TimerMode::Once), //Not real seconds either??
-            },
-            BulletOwner {
-                entity: self.from_entity,
-            },
-            self.team,
-            RemoveOnRespawn,
-        ));
+        let directions = self.weapon.directions(self.to_direction);
+        let projectiles = directions.len() as u32;
+        for direction in directions {
+            world.spawn((
+                Transform {
+                    translation: self.from_position.extend(2f32),
+                    ..default()
+                },
+                MoveSpeed(self.weapon.speed),
+                MoveDirection(direction),
+                Collider { radius: 2f32 },
+                DespawnAfter {
+                    timer: Timer::from_seconds(self.weapon.lifetime, TimerMode::Once),
+                },
+                Bullet {
+                    damage: self.weapon.damage,
+                    behaviour: self.weapon.behaviour.clone(),
+                    hits: Vec::new(),
+                    has_hit: false,
+                },
+                BulletOwner {
+                    entity: self.from_entity,
+                },
+                self.team.clone(),
+                RemoveOnRespawn,
+            ));
+        }
+        let tick = world.resource::<SimulationTime>().tick;
         world.send_event(EventBulletSpawn {
+            shooter: self.from_entity,
             origin: self.from_position,
+            projectiles,
+            tick,
         });
     }
 }
 
 pub trait CommandsSpawnBullet {
+    /// Fires `weapon` if its cooldown is over, and restarts it.
     fn spawn_bullet(
         &mut self,
         from_entity: Entity,
         from_position: Vec2,
         direction: Vec2,
         team: TeamIdx,
+        weapon: &Weapon,
         cooldown: &Cooldown,
-        time: &Res<Time>,
+        time: &SimulationTime,
     ) -> Result<&mut Self, ()>;
 }
 
@@ -87,8 +188,9 @@ impl CommandsSpawnBullet for Commands<'_, '_> {
         from_position: Vec2,
         direction: Vec2,
         team: TeamIdx,
+        weapon: &Weapon,
         cooldown: &Cooldown,
-        time: &Res<Time>,
+        time: &SimulationTime,
     ) -> Result<&mut Self, ()> {
         if direction == Vec2::ZERO {
             return Err(());
@@ -102,20 +204,104 @@ impl CommandsSpawnBullet for Commands<'_, '_> {
             from_position,
             to_direction: direction,
             team,
+            weapon: weapon.clone(),
+        });
+        self.entity(from_entity).insert(Cooldown {
+            start_time: time.elapsed_seconds(),
+            duration: weapon.cooldown(),
         });
         Ok(self)
     }
 }
 
+pub fn bullets_homing(
+    time: Res<SimulationTime>,
+    game_def: Res<GameDef>,
+    mut q_bullets: Query<(
+        &Transform,
+        &mut MoveDirection,
+        &Bullet,
+        &BulletOwner,
+        &TeamIdx,
+    )>,
+    q_targets: Query<(Entity, &Transform, &TeamIdx), With<Health>>,
+) {
+    for (transform, mut direction, bullet, owner, team) in q_bullets.iter_mut() {
+        let BulletBehaviour::Homing { turn_rate } = bullet.behaviour else {
+            continue;
+        };
+        let position = transform.translation.xy();
+        // Same targets as the ones it can hit, see `collisions_bullet_health`.
+        let Some(target) = q_targets
+            .iter()
+            .filter(|(target, _, target_team)| {
+                *target != owner.entity && game_def.can_damage(team, target_team)
+            })
+            .map(|(_, target, _)| target.translation.xy())
+            .min_by(|a, b| {
+                a.distance_squared(position)
+                    .total_cmp(&b.distance_squared(position))
+            })
+        else {
+            continue;
+        };
+        let angle = direction.0.angle_between(target - position);
+        if angle.is_nan() {
+            continue;
+        }
+        let max_turn = turn_rate * time.delta_seconds();
+        direction.0 = Vec2::from_angle(angle.clamp(-max_turn, max_turn)).rotate(direction.0);
+    }
+}
+
+/// Stops the bullets hitting the arena walls or its obstacles, or bounces them off.
+pub fn bullets_walls(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    mut q_bullets: Query<(
+        Entity,
+        &mut Transform,
+        &mut MoveDirection,
+        &Collider,
+        &mut Bullet,
+    )>,
+) {
+    for (entity, mut transform, mut direction, collider, mut bullet) in q_bullets.iter_mut() {
+        let Some((response, position, normal)) =
+            arena.hit(transform.translation.xy(), collider.radius)
+        else {
+            continue;
+        };
+        match (response, &mut bullet.behaviour) {
+            (BulletResponse::Reflect, _) => {}
+            (_, BulletBehaviour::Bouncing { bounces }) if *bounces > 0 => *bounces -= 1,
+            _ => {
+                commands.entity(entity).despawn();
+                continue;
+            }
+        }
+        let along = direction.0.dot(normal);
+        if along < 0f32 {
+            direction.0 -= 2f32 * along * normal;
+        }
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
 fn bullet_sounds(
-    time: Res<Time>,
     bullet_assets: Res<BulletAssets>,
     mut commands: Commands,
+    presented: Res<PresentedTick>,
     mut ev_bullets: EventReader<EventBulletSpawn>,
     listener: Query<&Transform, With<Player>>,
 ) {
-    for e in ev_bullets.iter() {
-        let listener = listener.single();
+    let players = listener
+        .iter()
+        .map(|transform| transform.translation.xy())
+        .collect::<Vec<_>>();
+    for e in ev_bullets.iter().filter(|e| presented.is_new(e.tick)) {
+        // Heard from the closest player, or from the center of the arena once they are all dead.
+        let listener = nearest_player(e.origin, &players).unwrap_or(Vec2::ZERO);
         commands.spawn((
             SpatialAudioBundle {
                 source: bullet_assets.pew1.clone(),
@@ -123,12 +309,11 @@ fn bullet_sounds(
                 spatial: SpatialSettings::new(
                     Transform::IDENTITY,
                     5f32,
-                    ((e.origin - listener.translation.xy()).normalize_or_zero() * (5f32 / 2f32))
-                        .extend(0f32),
+                    ((e.origin - listener).normalize_or_zero() * (5f32 / 2f32)).extend(0f32),
                 ),
             },
             DespawnAfter {
-                timer: Timer::from_seconds(2000., TimerMode::Once),
+                timer: Timer::from_seconds(2., TimerMode::Once),
             },
         ));
     }
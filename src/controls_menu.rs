@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+
+use crate::{
+    actions::{Action, ActionState, Binding, Bindings},
+    gamepad::ActiveGamepad,
+    menu::GameState,
+};
+
+/// Lists the [`Action`]s and lets the player rebind them, from the main menu.
+pub struct ControlsMenuPlugin;
+
+impl Plugin for ControlsMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControlsMenu>()
+            .add_systems(Startup, setup_controls_menu)
+            .add_systems(OnEnter(GameState::Controls), open_controls_menu)
+            .add_systems(OnExit(GameState::Controls), close_controls_menu)
+            .add_systems(
+                Update,
+                (controls_menu_actions, display_controls_menu)
+                    .chain()
+                    .run_if(in_state(GameState::Controls)),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+struct ControlsMenu {
+    /// Index in [`Action::ALL`].
+    selected: usize,
+    /// The next pressed button is bound to the selected action.
+    listening: bool,
+    /// The last rebind or reset was refused, see [`Bindings::menus_usable`].
+    refused: bool,
+}
+
+#[derive(Component)]
+struct ControlsMenuText;
+
+fn setup_controls_menu(mut commands: Commands) {
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 30.,
+                    ..default()
+                },
+            ),
+            style: Style {
+                display: Display::None,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::GRAY.with_a(0.5)),
+            ..default()
+        },
+        ControlsMenuText,
+    ));
+}
+
+fn open_controls_menu(
+    mut menu: ResMut<ControlsMenu>,
+    mut q_text: Query<&mut Style, With<ControlsMenuText>>,
+) {
+    *menu = ControlsMenu::default();
+    q_text.single_mut().display = Display::DEFAULT;
+}
+
+fn close_controls_menu(mut q_text: Query<&mut Style, With<ControlsMenuText>>) {
+    q_text.single_mut().display = Display::None;
+}
+
+/// First button pressed this frame on any device, gamepad buttons only on the [`ActiveGamepad`].
+fn just_pressed_binding(
+    keys: &Input<KeyCode>,
+    mouse_buttons: &Input<MouseButton>,
+    gamepad_buttons: &Input<GamepadButton>,
+    active_gamepad: &ActiveGamepad,
+) -> Option<Binding> {
+    keys.get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            mouse_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Mouse(*button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .find(|button| Some(button.gamepad) == active_gamepad.0)
+                .map(|button| Binding::Gamepad(button.button_type))
+        })
+}
+
+/// While listening, escape cancels instead of being bound.
+/// Otherwise confirm starts listening, reset binding resets the selected action and back saves.
+fn controls_menu_actions(
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    active_gamepad: Res<ActiveGamepad>,
+    action_state: Res<ActionState>,
+    mut menu: ResMut<ControlsMenu>,
+    mut bindings: ResMut<Bindings>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let action = Action::ALL[menu.selected];
+    if menu.listening {
+        if keys.just_pressed(KeyCode::Escape) {
+            menu.listening = false;
+        } else if let Some(binding) =
+            just_pressed_binding(&keys, &mouse_buttons, &gamepad_buttons, &active_gamepad)
+        {
+            menu.refused = !bindings.rebind(action, binding);
+            menu.listening = false;
+        }
+        return;
+    }
+    if action_state.just_pressed(Action::MoveUp) {
+        menu.selected = menu.selected.saturating_sub(1);
+    }
+    if action_state.just_pressed(Action::MoveDown) {
+        menu.selected = (menu.selected + 1).min(Action::ALL.len() - 1);
+    }
+    if action_state.just_pressed(Action::Confirm) {
+        menu.listening = true;
+    } else if action_state.just_pressed(Action::ResetBinding) {
+        menu.refused = !bindings.reset(action);
+    } else if action_state.just_pressed(Action::Back) {
+        bindings.save();
+        game_state.set(GameState::MainMenu);
+    }
+}
+
+fn display_controls_menu(
+    menu: Res<ControlsMenu>,
+    bindings: Res<Bindings>,
+    mut q_text: Query<&mut Text, With<ControlsMenuText>>,
+) {
+    if !menu.is_changed() && !bindings.is_changed() {
+        return;
+    }
+    let mut value = format!(
+        "Controls\n{}: rebind, {}: reset, {}: save and return\n\n",
+        bindings.labels(Action::Confirm),
+        bindings.labels(Action::ResetBinding),
+        bindings.labels(Action::Back)
+    );
+    if menu.refused {
+        value += "Refused: MoveUp, MoveDown, Confirm and Back each need a key and a pad button \
+            of their own\n\n";
+    }
+    for (i, action) in Action::ALL.iter().enumerate() {
+        let marker = if i == menu.selected { ">" } else { " " };
+        let labels = if i == menu.selected && menu.listening {
+            "press a button, Escape to cancel".to_string()
+        } else {
+            bindings.labels(*action)
+        };
+        value += &format!("{marker} {action:?}: {labels}\n");
+    }
+    q_text.single_mut().sections[0].value = value;
+}
@@ -1,30 +1,27 @@
 use bevy::prelude::*;
 
-use crate::menu::GameState;
+use crate::{simulation_time::SimulationTime, GameSet};
 
 pub struct DespawnAfterPlugin;
 
 impl Plugin for DespawnAfterPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            PostUpdate,
-            despawn_after.run_if(in_state(GameState::Playing)),
-        );
+        app.add_systems(FixedUpdate, despawn_after.in_set(GameSet::Despawn));
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct DespawnAfter {
     pub timer: Timer,
 }
 
 fn despawn_after(
     mut commands: Commands,
-    time: Res<Time>,
+    time: Res<SimulationTime>,
     mut q_des: Query<(Entity, &mut DespawnAfter)>,
 ) {
     for (e, mut d) in q_des.iter_mut() {
-        d.timer.tick(time.elapsed());
+        d.timer.tick(time.delta);
         if d.timer.finished() {
             commands.entity(e).despawn();
         }
@@ -4,24 +4,52 @@ use bevy::{math::Vec3Swizzles, prelude::*};
 use bevy_vector_shapes::prelude::*;
 
 use crate::{
-    movement::{MoveDirection, MoveTarget},
+    ai::Ai,
+    archetypes::Appearance,
+    arena::{Arena, BulletResponse, Shape},
+    movement::MoveDirection,
+    player::Player,
+    simulation_time::SimulationTime,
     Cooldown, Health, HealthPickup, TeamIdx, Teams,
 };
 
 pub fn draw(
     teams: Res<Teams>,
     mut gizmos: Gizmos,
-    q_movers: Query<(&Transform, &TeamIdx), With<MoveTarget>>,
+    q_movers: Query<(&Transform, &TeamIdx, Option<&Appearance>), Or<(With<Player>, With<Ai>)>>,
 ) {
-    for (transform, team) in q_movers.iter() {
-        gizmos.circle_2d(transform.translation.xy(), 5f32, teams.colors[team.0].0);
+    for (transform, team, appearance) in q_movers.iter() {
+        let (color, radius) = match appearance {
+            Some(appearance) => (appearance.color, appearance.radius),
+            None => (teams.colors[team.0].0, 5f32),
+        };
+        gizmos.circle_2d(transform.translation.xy(), radius, color);
+    }
+}
+
+/// Outlines of the arena walls and obstacles, brighter for the ones bullets bounce off.
+pub fn draw_arena(arena: Res<Arena>, mut gizmos: Gizmos) {
+    gizmos.rect_2d(Vec2::ZERO, 0f32, arena.half_size * 2f32, Color::GRAY);
+    for obstacle in arena.obstacles.iter() {
+        let color = match obstacle.bullets {
+            BulletResponse::Absorb => Color::GRAY,
+            BulletResponse::Reflect => Color::CYAN * 2f32,
+        };
+        match obstacle.shape {
+            Shape::Circle { center, radius } => {
+                gizmos.circle_2d(center, radius, color);
+            }
+            Shape::Rect { center, half_size } => {
+                gizmos.rect_2d(center, 0f32, half_size * 2f32, color);
+            }
+        }
     }
 }
 
 pub fn draw_bullets(
     teams: Res<Teams>,
     mut gizmos: Gizmos,
-    q_movers: Query<(&Transform, &TeamIdx), With<MoveDirection>>,
+    q_movers: Query<(&Transform, &TeamIdx), (With<MoveDirection>, Without<Ai>)>,
 ) {
     for (transform, team) in q_movers.iter() {
         gizmos.circle_2d(transform.translation.xy(), 2f32, teams.colors[team.0].1);
@@ -29,7 +57,7 @@ pub fn draw_bullets(
 }
 
 pub fn draw_health(mut painter: ShapePainter, q_movers: Query<(&Transform, &Health, &TeamIdx)>) {
-    for (transform, health, team) in q_movers.iter() {
+    for (transform, health, _team) in q_movers.iter() {
         if health.max <= health.current {
             continue;
         }
@@ -46,11 +74,11 @@ pub fn draw_health(mut painter: ShapePainter, q_movers: Query<(&Transform, &Heal
     }
 }
 pub fn draw_cooldown(
-    time: Res<Time>,
+    time: Res<SimulationTime>,
     mut painter: ShapePainter,
     q_movers: Query<(&Transform, &Cooldown, &TeamIdx)>,
 ) {
-    for (transform, cooldown, team) in q_movers.iter() {
+    for (transform, cooldown, _team) in q_movers.iter() {
         if cooldown.start_time + cooldown.duration < time.elapsed_seconds() {
             continue;
         }
@@ -0,0 +1,444 @@
+use bevy::{input::mouse::MouseWheel, math::Vec3Swizzles, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    actions::{Action, ActionState, Bindings},
+    arena::{Arena, ArenaAssets, BulletResponse, Obstacle, PickupSpawner, SelectedArena, Shape},
+    arena_generator::{add_generated_arena, GENERATED},
+    menu::GameState,
+    player::cursor_world_position,
+    rng::GameRng,
+    storage, PlayTestStart,
+};
+
+/// Storage names of the arenas saved from the editor, added to the loaded ones at startup.
+const SAVED_ARENAS: &str = "saved_arenas";
+
+/// Places obstacles, enemy spawn points and pickup spawners in the selected [`Arena`] with the mouse,
+/// from the main menu.
+///
+/// The [`Arena`] resource is edited, then saved to and loaded from `assets/arenas/<name>.arena.ron`,
+/// see [`storage`]. Saved arenas replace the loaded ones of the same name, in the menu too.
+/// Play-testing starts from the camera, with the edited arena in place of the loaded one
+/// until the game quits.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ArenaEditor>()
+            .add_systems(Startup, setup_editor)
+            .add_systems(
+                OnExit(GameState::Loading),
+                add_saved_arenas.before(add_generated_arena),
+            )
+            .add_systems(OnEnter(GameState::Editor), open_editor)
+            .add_systems(OnExit(GameState::Editor), close_editor)
+            .add_systems(
+                Update,
+                (pan_camera, editor_actions, display_editor, draw_editor)
+                    .chain()
+                    .run_if(in_state(GameState::Editor)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Tool {
+    #[default]
+    Circle,
+    Rect,
+    EnemySpawn,
+    PickupSpawner,
+}
+
+impl Tool {
+    fn next(self) -> Self {
+        match self {
+            Tool::Circle => Tool::Rect,
+            Tool::Rect => Tool::EnemySpawn,
+            Tool::EnemySpawn => Tool::PickupSpawner,
+            Tool::PickupSpawner => Tool::Circle,
+        }
+    }
+}
+
+#[derive(Resource, Debug)]
+struct ArenaEditor {
+    tool: Tool,
+    /// Radius of the circles, half length of the rects.
+    size: f32,
+    /// Rects are placed along the y axis rather than the x axis.
+    vertical: bool,
+    bullets: BulletResponse,
+    /// Result of the last save or load.
+    status: String,
+}
+
+impl Default for ArenaEditor {
+    fn default() -> Self {
+        Self {
+            tool: Tool::default(),
+            size: 40f32,
+            vertical: false,
+            bullets: BulletResponse::default(),
+            status: String::new(),
+        }
+    }
+}
+
+impl ArenaEditor {
+    /// Half thickness of the rects.
+    const THICKNESS: f32 = 16f32;
+    /// Distance from the cursor within which spawn points and pickup spawners are removed.
+    const PICK_RADIUS: f32 = 20f32;
+    const PICKUP_AMOUNT: f32 = 0.25f32;
+    const PICKUP_INTERVAL: f32 = 20f32;
+
+    fn shape(&self, center: Vec2) -> Shape {
+        match (self.tool, self.vertical) {
+            (Tool::Rect, false) => Shape::Rect {
+                center,
+                half_size: Vec2::new(self.size, Self::THICKNESS),
+            },
+            (Tool::Rect, true) => Shape::Rect {
+                center,
+                half_size: Vec2::new(Self::THICKNESS, self.size),
+            },
+            _ => Shape::Circle {
+                center,
+                radius: self.size,
+            },
+        }
+    }
+
+    fn place(&self, arena: &mut Arena, position: Vec2) {
+        match self.tool {
+            Tool::Circle | Tool::Rect => arena.obstacles.push(Obstacle {
+                shape: self.shape(position),
+                bullets: self.bullets,
+            }),
+            Tool::EnemySpawn => arena.enemy_spawns.push(position),
+            Tool::PickupSpawner => arena.pickup_spawners.push(PickupSpawner {
+                position,
+                amount: Self::PICKUP_AMOUNT,
+                interval: Self::PICKUP_INTERVAL,
+                next_in: 0f32,
+            }),
+        }
+    }
+
+    /// Removes a spawn point or pickup spawner near `position`, or else an obstacle under it.
+    fn remove(arena: &mut Arena, position: Vec2) {
+        let near = |point: Vec2| point.distance(position) < Self::PICK_RADIUS;
+        if let Some(index) = arena.enemy_spawns.iter().position(|spawn| near(*spawn)) {
+            arena.enemy_spawns.remove(index);
+        } else if let Some(index) = arena
+            .pickup_spawners
+            .iter()
+            .position(|spawner| near(spawner.position))
+        {
+            arena.pickup_spawners.remove(index);
+        } else if let Some(index) = arena
+            .obstacles
+            .iter()
+            .rposition(|obstacle| obstacle.shape.push_out(position, 0f32).is_some())
+        {
+            arena.obstacles.remove(index);
+        }
+    }
+}
+
+/// Name the arena is saved under, without the `.ron` added by [`storage`].
+fn storage_name(arena: &Arena) -> String {
+    format!(
+        "assets/arenas/{}.arena",
+        arena.name.to_lowercase().replace(' ', "_")
+    )
+}
+
+fn saved_arenas() -> Vec<String> {
+    storage::read(SAVED_ARENAS)
+        .and_then(|saved| ron::from_str(&saved).ok())
+        .unwrap_or_default()
+}
+
+/// Replaces the loaded arena of the same name, or adds it after the others.
+fn add_or_replace(arena: Arena, arena_assets: &mut ArenaAssets, arenas: &mut Assets<Arena>) {
+    let handle = arena_assets
+        .arenas
+        .iter()
+        .find(|handle| {
+            arenas
+                .get(handle)
+                .is_some_and(|loaded| loaded.name == arena.name)
+        })
+        .cloned();
+    match handle.and_then(|handle| arenas.get_mut(&handle)) {
+        Some(loaded) => *loaded = arena,
+        None => arena_assets.arenas.push(arenas.add(arena)),
+    }
+}
+
+/// Saves the arena and keeps it in the loaded ones, so it can be picked from the menu.
+fn save_arena(
+    arena: &Arena,
+    arena_assets: &mut ArenaAssets,
+    arenas: &mut Assets<Arena>,
+) -> Result<(), ron::Error> {
+    let name = storage_name(arena);
+    storage::write(&name, &ron::ser::to_string_pretty(arena, default())?);
+    let mut saved = saved_arenas();
+    if !saved.contains(&name) {
+        saved.push(name);
+        storage::write(SAVED_ARENAS, &ron::to_string(&saved)?);
+    }
+    add_or_replace(arena.clone(), arena_assets, arenas);
+    Ok(())
+}
+
+/// Adds the arenas saved from the editor in a previous session, before the generated one.
+fn add_saved_arenas(mut arena_assets: ResMut<ArenaAssets>, mut arenas: ResMut<Assets<Arena>>) {
+    for name in saved_arenas() {
+        match storage::read(&name).map(|saved| ron::from_str(&saved)) {
+            Some(Ok(saved)) => add_or_replace(saved, &mut arena_assets, &mut arenas),
+            Some(Err(error)) => warn!("Couldn't read {name}.ron: {error}"),
+            None => warn!("{name}.ron is missing"),
+        }
+    }
+}
+
+#[derive(Component)]
+struct EditorText;
+
+fn setup_editor(mut commands: Commands) {
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 24.,
+                    ..default()
+                },
+            ),
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                top: Val::Percent(2.),
+                left: Val::Percent(2.),
+                ..default()
+            },
+            ..default()
+        },
+        EditorText,
+    ));
+}
+
+/// Starts from the selected arena, unless it is the one already played or edited,
+/// which may have been edited without saving.
+///
+/// The generated arena is renamed after its seed, so it is saved as an arena of its own
+/// rather than replaced when the seed changes.
+fn open_editor(
+    mut editor: ResMut<ArenaEditor>,
+    selected_arena: Res<SelectedArena>,
+    arena_assets: Res<ArenaAssets>,
+    arenas: Res<Assets<Arena>>,
+    rng: Res<GameRng>,
+    mut arena: ResMut<Arena>,
+    mut q_text: Query<&mut Style, With<EditorText>>,
+) {
+    if let Some(selected) = arena_assets.get(&selected_arena.0, &arenas) {
+        let mut selected = selected.clone();
+        if selected.name == GENERATED {
+            selected.name = format!("{GENERATED} {}", rng.seed());
+        }
+        if selected.name != arena.name {
+            *arena = selected;
+        }
+    }
+    editor.status.clear();
+    q_text.single_mut().display = Display::DEFAULT;
+}
+
+fn close_editor(
+    mut q_text: Query<&mut Style, With<EditorText>>,
+    mut q_camera: Query<&mut Transform, With<Camera>>,
+) {
+    q_text.single_mut().display = Display::None;
+    for mut transform in q_camera.iter_mut() {
+        transform.translation.x = 0f32;
+        transform.translation.y = 0f32;
+    }
+}
+
+/// The move actions pan the camera, as the view is smaller than most arenas.
+fn pan_camera(
+    time: Res<Time>,
+    action_state: Res<ActionState>,
+    mut q_camera: Query<&mut Transform, With<Camera>>,
+) {
+    const SPEED: f32 = 600f32;
+    let direction = action_state.direction(
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+    );
+    for mut transform in q_camera.iter_mut() {
+        transform.translation += (direction * SPEED * time.delta_seconds()).extend(0f32);
+    }
+}
+
+fn editor_actions(
+    mut mouse_wheel: EventReader<MouseWheel>,
+    action_state: Res<ActionState>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&GlobalTransform, &Camera)>,
+    mut editor: ResMut<ArenaEditor>,
+    mut arena: ResMut<Arena>,
+    mut arena_assets: ResMut<ArenaAssets>,
+    mut arenas: ResMut<Assets<Arena>>,
+    mut selected_arena: ResMut<SelectedArena>,
+    mut play_test_start: ResMut<PlayTestStart>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if action_state.just_pressed(Action::NextTool) {
+        editor.tool = editor.tool.next();
+    }
+    let scroll = mouse_wheel.iter().map(|ev| ev.y.signum()).sum::<f32>();
+    if scroll != 0f32 {
+        editor.size = (editor.size + scroll * 4f32).clamp(8f32, 400f32);
+    }
+    if action_state.just_pressed(Action::ToggleBullets) {
+        editor.bullets = match editor.bullets {
+            BulletResponse::Absorb => BulletResponse::Reflect,
+            BulletResponse::Reflect => BulletResponse::Absorb,
+        };
+    }
+    if action_state.just_pressed(Action::RotateRect) {
+        editor.vertical = !editor.vertical;
+    }
+    if let Some(action) = [Action::Place, Action::Remove]
+        .into_iter()
+        .find(|action| action_state.just_pressed(*action))
+    {
+        // Gamepads have no cursor, they edit the center of the view.
+        let position = if action_state.pressed_on_gamepad(action) {
+            camera_position(&camera)
+        } else {
+            cursor_world_position(q_windows.single(), &camera)
+        };
+        match (action, position) {
+            (Action::Place, Some(position)) => editor.place(&mut arena, position),
+            (_, Some(position)) => ArenaEditor::remove(&mut arena, position),
+            _ => {}
+        }
+    }
+    if action_state.just_pressed(Action::SaveArena) {
+        editor.status = match save_arena(&arena, &mut arena_assets, &mut arenas) {
+            Ok(()) => format!("Saved {}.ron", storage_name(&arena)),
+            Err(error) => format!("Couldn't serialize the arena: {error}"),
+        };
+    } else if action_state.just_pressed(Action::LoadArena) {
+        editor.status =
+            match storage::read(&storage_name(&arena)).map(|saved| ron::from_str(&saved)) {
+                Some(Ok(loaded)) => {
+                    let status = format!("Loaded {}.ron", storage_name(&arena));
+                    *arena = loaded;
+                    status
+                }
+                Some(Err(error)) => format!("Couldn't read {}.ron: {error}", storage_name(&arena)),
+                None => format!("Nothing saved as {}.ron", storage_name(&arena)),
+            };
+    } else if action_state.just_pressed(Action::Confirm) {
+        // Replaces the loaded arena of the same name, so the run started picks the copy.
+        add_or_replace(arena.clone(), &mut arena_assets, &mut arenas);
+        selected_arena.0 = arena.name.clone();
+        play_test_start.0 = camera_position(&camera)
+            .map(|position| position.clamp(-arena.half_size, arena.half_size));
+        game_state.set(GameState::Playing);
+    } else if action_state.just_pressed(Action::Back) {
+        game_state.set(GameState::MainMenu);
+    }
+}
+
+/// Center of the view, where play-testing starts.
+fn camera_position(camera: &Query<(&GlobalTransform, &Camera)>) -> Option<Vec2> {
+    camera
+        .iter()
+        .next()
+        .map(|(transform, _)| transform.translation().xy())
+}
+
+fn display_editor(
+    editor: Res<ArenaEditor>,
+    arena: Res<Arena>,
+    bindings: Res<Bindings>,
+    mut q_text: Query<&mut Text, With<EditorText>>,
+) {
+    if !editor.is_changed() && !arena.is_changed() {
+        return;
+    }
+    q_text.single_mut().sections[0].value = format!(
+        "Editing {}, move to pan\n\
+        {}: tool ({:?})\n\
+        {}: place, {}: remove, wheel: size ({:.0})\n\
+        {}: bullets ({:?}), {}: rotate rects\n\
+        {}: save, {}: load, {}: play-test from the center, {}: main menu\n\
+        {}",
+        arena.name,
+        bindings.labels(Action::NextTool),
+        editor.tool,
+        bindings.labels(Action::Place),
+        bindings.labels(Action::Remove),
+        editor.size,
+        bindings.labels(Action::ToggleBullets),
+        editor.bullets,
+        bindings.labels(Action::RotateRect),
+        bindings.labels(Action::SaveArena),
+        bindings.labels(Action::LoadArena),
+        bindings.labels(Action::Confirm),
+        bindings.labels(Action::Back),
+        editor.status
+    );
+}
+
+/// The arena itself is drawn by [`crate::draw::draw_arena`], this adds what the game doesn't show
+/// and a preview of what a click places.
+fn draw_editor(
+    editor: Res<ArenaEditor>,
+    arena: Res<Arena>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&GlobalTransform, &Camera)>,
+    mut gizmos: Gizmos,
+) {
+    for spawn in arena.enemy_spawns.iter() {
+        gizmos.circle_2d(*spawn, 8f32, Color::RED * 2f32);
+    }
+    for spawner in arena.pickup_spawners.iter() {
+        gizmos.circle_2d(spawner.position, 6f32, Color::BLUE * 3f32);
+    }
+    for spawn in arena.player_spawns.iter() {
+        gizmos.circle_2d(*spawn, 5f32, Color::GREEN * 2f32);
+    }
+    if let Some(start) = camera_position(&camera) {
+        gizmos.circle_2d(start, 5f32, Color::GREEN.with_a(0.5));
+    }
+    let Some(cursor) = cursor_world_position(q_windows.single(), &camera) else {
+        return;
+    };
+    let color = Color::WHITE.with_a(0.5);
+    match (editor.tool, editor.shape(cursor)) {
+        (Tool::EnemySpawn, _) => {
+            gizmos.circle_2d(cursor, 8f32, color);
+        }
+        (Tool::PickupSpawner, _) => {
+            gizmos.circle_2d(cursor, 6f32, color);
+        }
+        (_, Shape::Circle { center, radius }) => {
+            gizmos.circle_2d(center, radius, color);
+        }
+        (_, Shape::Rect { center, half_size }) => {
+            gizmos.rect_2d(center, 0f32, half_size * 2f32, color);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use bevy::{
+    input::gamepad::{GamepadConnection, GamepadConnectionEvent},
+    math::Vec3Swizzles,
+    prelude::*,
+};
+
+use crate::{
+    actions::{Action, LocalPlayers},
+    menu::LastActivity,
+    movement::move_actions,
+    player::{fire_actions, Player, PlayerInput, AIM_DISTANCE},
+    replay::is_playing_back,
+    GameMode, GameSet,
+};
+
+/// Twin-stick controls: left stick to move, right stick to aim and fire.
+/// Its buttons are bound to actions like the keyboard ones, see [`crate::actions`].
+///
+/// The first connected gamepad plays alone and drives the menus, another one takes over when it is unplugged.
+/// In co-op each player gets their own, see [`crate::actions::LocalPlayers`].
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveGamepad>()
+            .init_resource::<GamepadControls>()
+            .add_systems(PreUpdate, gamepad_connections)
+            .add_systems(
+                FixedUpdate,
+                gamepad_twin_stick
+                    .after(fire_actions)
+                    .after(move_actions)
+                    .in_set(GameSet::Input)
+                    .run_if(not(is_playing_back)),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct ActiveGamepad(pub Option<Gamepad>);
+
+#[derive(Resource, Debug)]
+pub struct GamepadControls {
+    /// Stick deflections under this length are ignored, between 0 and 1.
+    pub move_dead_zone: f32,
+    /// Right stick deflection needed to aim and fire.
+    pub aim_dead_zone: f32,
+}
+
+impl Default for GamepadControls {
+    fn default() -> Self {
+        Self {
+            move_dead_zone: 0.2,
+            aim_dead_zone: 0.35,
+        }
+    }
+}
+
+fn gamepad_connections(
+    gamepads: Res<Gamepads>,
+    mut active_gamepad: ResMut<ActiveGamepad>,
+    mut events: EventReader<GamepadConnectionEvent>,
+) {
+    for ev in events.iter() {
+        match &ev.connection {
+            GamepadConnection::Connected(info) => {
+                info!("Gamepad {} connected: {}", ev.gamepad.id, info.name);
+                if active_gamepad.0.is_none() {
+                    active_gamepad.0 = Some(ev.gamepad);
+                }
+            }
+            GamepadConnection::Disconnected => {
+                info!("Gamepad {} disconnected", ev.gamepad.id);
+                if active_gamepad.0 == Some(ev.gamepad) {
+                    active_gamepad.0 = gamepads.iter().find(|gamepad| *gamepad != ev.gamepad);
+                }
+            }
+        }
+    }
+}
+
+/// Scales the stick deflection so it goes from 0 at the dead zone to 1 at full deflection.
+fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+    stick / length * ((length - dead_zone) / (1f32 - dead_zone)).min(1f32)
+}
+
+/// Overrides the keyboard and mouse input, only when the sticks or a gamepad fire button are used.
+fn gamepad_twin_stick(
+    local_players: Res<LocalPlayers>,
+    controls: Res<GamepadControls>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut q_inputs: Query<(&Player, &Transform, &mut PlayerInput)>,
+    mut last_activity: ResMut<LastActivity>,
+    mut last_aims: Local<[Option<Vec2>; GameMode::MAX_PLAYERS]>,
+) {
+    for (player, transform, mut input) in q_inputs.iter_mut() {
+        let Some((devices, action_state)) = local_players.get(player.0) else {
+            continue;
+        };
+        let Some(gamepad) = devices.gamepad else {
+            continue;
+        };
+        let stick = |x, y| {
+            Vec2::new(
+                axes.get(GamepadAxis::new(gamepad, x)).unwrap_or_default(),
+                axes.get(GamepadAxis::new(gamepad, y)).unwrap_or_default(),
+            )
+        };
+        let direction = apply_dead_zone(
+            stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+            controls.move_dead_zone,
+        );
+        let aim = apply_dead_zone(
+            stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+            controls.aim_dead_zone,
+        );
+        let last_aim = &mut last_aims[player.0];
+        if aim != Vec2::ZERO {
+            *last_aim = Some(aim.normalize());
+        }
+        let firing = aim != Vec2::ZERO || action_state.pressed_on_gamepad(Action::Fire);
+        if direction == Vec2::ZERO && !firing {
+            continue;
+        }
+        last_activity.0.reset();
+        if direction != Vec2::ZERO {
+            input.direction = direction;
+        }
+        if firing {
+            // Without any aim yet, the trigger shoots where the player is going.
+            let aim = last_aim.unwrap_or(input.direction.try_normalize().unwrap_or(Vec2::X));
+            input.fire_at = Some(transform.translation.xy() + aim * AIM_DISTANCE);
+        }
+    }
+}
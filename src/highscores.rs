@@ -0,0 +1,176 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{Action, ActionState},
+    arena::Arena,
+    menu::GameState,
+    rng::GameRng,
+    stats::RunStats,
+    storage, GameMode,
+};
+
+const STORAGE_NAME: &str = "highscores";
+
+/// Keeps the best runs in [`HighScores`], asking for a name after a run which made it to the table.
+pub struct HighScoresPlugin;
+
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HighScores::load())
+            .init_resource::<NameEntry>()
+            .add_systems(OnEnter(GameState::GameOver), start_name_entry)
+            .add_systems(Update, enter_name);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HighScore {
+    pub name: String,
+    pub mode: GameMode,
+    /// Name of the [`crate::arena::Arena`], the default one for the runs saved before there was
+    /// a choice, which were all played in it.
+    #[serde(default = "default_arena")]
+    pub arena: String,
+    pub seed: u64,
+    pub score: u64,
+    pub kills: u32,
+    pub survival_time: f32,
+}
+
+fn default_arena() -> String {
+    Arena::default().name
+}
+
+/// Every high score, whatever its mode, arena and seed, saved after each change.
+#[derive(Resource, Serialize, Deserialize, Debug, Default)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    /// Runs kept for each mode, arena and seed.
+    pub const TABLE_SIZE: usize = 10;
+
+    /// Best runs of this mode, arena and seed first.
+    pub fn table(&self, mode: GameMode, arena: &str, seed: u64) -> Vec<&HighScore> {
+        let mut table = self
+            .entries
+            .iter()
+            .filter(|entry| entry.mode == mode && entry.arena == arena && entry.seed == seed)
+            .collect::<Vec<_>>();
+        table.sort_by(|a, b| b.score.cmp(&a.score));
+        table
+    }
+
+    pub fn qualifies(&self, mode: GameMode, arena: &str, seed: u64, score: u64) -> bool {
+        let table = self.table(mode, arena, seed);
+        score > 0
+            && (table.len() < Self::TABLE_SIZE
+                || table.last().is_some_and(|lowest| lowest.score < score))
+    }
+
+    /// Adds the run, dropping the lowest one of its table if it's full.
+    pub fn insert(&mut self, high_score: HighScore) {
+        let (mode, arena, seed) = (high_score.mode, high_score.arena.clone(), high_score.seed);
+        self.entries.push(high_score);
+        self.entries.sort_by(|a, b| b.score.cmp(&a.score));
+        let mut kept = 0;
+        self.entries.retain(|entry| {
+            if entry.mode != mode || entry.arena != arena || entry.seed != seed {
+                return true;
+            }
+            kept += 1;
+            kept <= Self::TABLE_SIZE
+        });
+    }
+
+    /// Starts from an empty table when there is nothing saved or it can't be read.
+    pub fn load() -> Self {
+        let Some(saved) = storage::read(STORAGE_NAME) else {
+            return Self::default();
+        };
+        ron::from_str(&saved).unwrap_or_else(|error| {
+            warn!("Couldn't read the high scores: {error}");
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) {
+        match ron::ser::to_string_pretty(self, default()) {
+            Ok(serialized) => storage::write(STORAGE_NAME, &serialized),
+            Err(error) => warn!("Couldn't serialize the high scores: {error}"),
+        }
+    }
+}
+
+/// Name typed so far for the run which just ended, while it is being entered.
+#[derive(Resource, Debug, Default)]
+pub struct NameEntry(pub Option<String>);
+
+impl NameEntry {
+    pub const MAX_LENGTH: usize = 12;
+}
+
+pub fn is_entering_name(name_entry: Res<NameEntry>) -> bool {
+    name_entry.0.is_some()
+}
+
+fn start_name_entry(
+    stats: Res<RunStats>,
+    mode: Res<GameMode>,
+    arena: Res<Arena>,
+    rng: Res<GameRng>,
+    high_scores: Res<HighScores>,
+    mut name_entry: ResMut<NameEntry>,
+) {
+    if high_scores.qualifies(*mode, &arena.name, rng.seed(), stats.score) {
+        name_entry.0 = Some(String::new());
+    }
+}
+
+pub fn enter_name(
+    mut characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut action_state: ResMut<ActionState>,
+    stats: Res<RunStats>,
+    mode: Res<GameMode>,
+    arena: Res<Arena>,
+    rng: Res<GameRng>,
+    mut high_scores: ResMut<HighScores>,
+    mut name_entry: ResMut<NameEntry>,
+) {
+    // Always read, so the keys pressed while playing aren't typed in once the entry starts.
+    let typed = characters.iter().map(|ev| ev.char).collect::<Vec<_>>();
+    if name_entry.0.is_none() {
+        return;
+    }
+    let Some(name) = name_entry.0.as_mut() else {
+        return;
+    };
+    for char in typed {
+        if !char.is_control() && name.chars().count() < NameEntry::MAX_LENGTH {
+            name.push(char);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        name.pop();
+    }
+    if !action_state.just_pressed(Action::Confirm) {
+        return;
+    }
+    // Otherwise the menu would also start a new game this frame.
+    action_state.consume(Action::Confirm);
+    let name = name.trim();
+    high_scores.insert(HighScore {
+        name: if name.is_empty() { "???" } else { name }.to_string(),
+        mode: *mode,
+        arena: arena.name.clone(),
+        seed: rng.seed(),
+        score: stats.score,
+        kills: stats.kills,
+        survival_time: stats.survival_time,
+    });
+    high_scores.save();
+    name_entry.0 = None;
+}
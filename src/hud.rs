@@ -0,0 +1,226 @@
+use bevy::prelude::*;
+
+use crate::{
+    boss::Boss,
+    rollback::PresentedTick,
+    stats::RunStats,
+    waves::{EventWaveCleared, EventWaveStarted},
+    GameMode, Health, RemainingLives,
+};
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            (setup_wave_banner, setup_run_stats, setup_boss_bar),
+        )
+        .add_systems(
+            Update,
+            (display_wave_banner, display_run_stats, display_boss_bar),
+        );
+    }
+}
+
+/// Announces wave starts and clears, for a few seconds.
+#[derive(Component)]
+struct WaveBanner {
+    timer: Timer,
+}
+
+fn setup_wave_banner(mut commands: Commands) {
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 40.,
+                    ..default()
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(10.),
+                width: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                display: Display::None,
+                ..default()
+            },
+            ..default()
+        }
+        .with_text_alignment(TextAlignment::Center),
+        WaveBanner {
+            timer: Timer::from_seconds(3., TimerMode::Once),
+        },
+    ));
+}
+
+/// The waves started and cleared again by a rollback were already announced.
+fn display_wave_banner(
+    time: Res<Time>,
+    presented: Res<PresentedTick>,
+    mut events_started: EventReader<EventWaveStarted>,
+    mut events_cleared: EventReader<EventWaveCleared>,
+    mut q_banner: Query<(&mut Text, &mut Style, &mut WaveBanner)>,
+) {
+    let (mut text, mut style, mut banner) = q_banner.single_mut();
+    let message = events_started
+        .iter()
+        .filter(|ev| presented.is_new(ev.tick))
+        .map(|ev| format!("Wave {}: {}", ev.wave + 1, ev.name))
+        .chain(
+            events_cleared
+                .iter()
+                .filter(|ev| presented.is_new(ev.tick))
+                .map(|ev| format!("Wave {} cleared!", ev.wave + 1)),
+        )
+        .last();
+    if let Some(message) = message {
+        text.sections[0].value = message;
+        style.display = Display::DEFAULT;
+        banner.timer.reset();
+    }
+    banner.timer.tick(time.delta());
+    if banner.timer.just_finished() {
+        style.display = Display::None;
+    }
+}
+
+/// Score, combo and kills of the current run, and the lives left in co-op.
+#[derive(Component)]
+struct RunStatsText;
+
+fn setup_run_stats(mut commands: Commands) {
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 30.,
+                    ..default()
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(2.),
+                left: Val::Percent(2.),
+                ..default()
+            },
+            ..default()
+        },
+        RunStatsText,
+    ));
+}
+
+fn display_run_stats(
+    stats: Res<RunStats>,
+    mode: Res<GameMode>,
+    lives: Res<RemainingLives>,
+    mut q_text: Query<&mut Text, With<RunStatsText>>,
+) {
+    if !stats.is_changed() && !lives.is_changed() {
+        return;
+    }
+    let mut value = format!(
+        "Score: {}  x{:.2}\nKills: {}\nAccuracy: {:.0}%",
+        stats.score,
+        stats.combo,
+        stats.kills,
+        stats.accuracy() * 100f32
+    );
+    match (*mode, &*lives) {
+        (GameMode::Solo, _) => {}
+        (_, RemainingLives::Shared(left)) => value += &format!("\nLives left: {left}"),
+        (_, RemainingLives::Split(lives)) => {
+            value += "\nLives left:";
+            for (index, left) in lives.iter().enumerate() {
+                value += &format!("  P{} {left}", index + 1);
+            }
+        }
+    }
+    q_text.single_mut().sections[0].value = value;
+}
+
+/// Name and health of the current boss, hidden when there is none.
+#[derive(Component)]
+struct BossBar;
+
+#[derive(Component)]
+struct BossBarName;
+
+#[derive(Component)]
+struct BossBarFill;
+
+fn setup_boss_bar(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(2.),
+                    left: Val::Percent(30.),
+                    width: Val::Percent(40.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    display: Display::None,
+                    ..default()
+                },
+                ..default()
+            },
+            BossBar,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 30.,
+                        ..default()
+                    },
+                ),
+                BossBarName,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.),
+                        height: Val::Px(16.),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.2, 0.2, 0.2).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.),
+                                height: Val::Percent(100.),
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.9, 0.1, 0.1).into(),
+                            ..default()
+                        },
+                        BossBarFill,
+                    ));
+                });
+        });
+}
+
+fn display_boss_bar(
+    q_bosses: Query<(&Boss, &Health)>,
+    mut q_bar: Query<&mut Style, (With<BossBar>, Without<BossBarFill>)>,
+    mut q_name: Query<&mut Text, With<BossBarName>>,
+    mut q_fill: Query<&mut Style, (With<BossBarFill>, Without<BossBar>)>,
+) {
+    let mut bar = q_bar.single_mut();
+    let Some((boss, health)) = q_bosses.iter().next() else {
+        bar.display = Display::None;
+        return;
+    };
+    bar.display = Display::Flex;
+    q_name.single_mut().sections[0].value = boss.name.to_uppercase();
+    q_fill.single_mut().width =
+        Val::Percent((health.current / health.max).clamp(0f32, 1f32) * 100f32);
+}
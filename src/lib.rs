@@ -0,0 +1,680 @@
+#![allow(
+    clippy::too_many_arguments,
+    clippy::type_complexity,
+    clippy::result_unit_err
+)]
+
+pub mod actions;
+pub mod ai;
+pub mod archetypes;
+pub mod arena;
+pub mod arena_generator;
+pub mod behaviour;
+pub mod boss;
+pub mod bullets;
+pub mod controls_menu;
+pub mod despawn_after;
+pub mod draw;
+pub mod editor;
+pub mod gamepad;
+pub mod highscores;
+pub mod hud;
+pub mod menu;
+pub mod movement;
+pub mod nav_grid;
+pub mod navigation;
+pub mod network;
+pub mod player;
+pub mod presentation;
+pub mod replay;
+pub mod rng;
+pub mod rollback;
+pub mod ron_asset;
+pub mod simulation_time;
+pub mod spatial_hash;
+pub mod stats;
+pub mod steering;
+pub mod storage;
+pub mod utils;
+pub mod waves;
+
+use std::collections::HashSet;
+
+use bevy::{math::Vec3Swizzles, prelude::*};
+
+use archetypes::{DropTable, EnemyArchetypesPlugin, Pickup};
+use arena::{Arena, ArenaAssets, ArenaPlugin, PickupSpawner, SelectedArena};
+use bullets::*;
+use despawn_after::*;
+use menu::*;
+use movement::*;
+use navigation::FlowField;
+use network::NetworkPlugin;
+use player::*;
+use rand::Rng;
+use replay::ReplayPlugin;
+use rng::GameRng;
+use rollback::RollbackPlugin;
+use serde::{Deserialize, Serialize};
+use simulation_time::*;
+use spatial_hash::{rebuild_spatial_hash, Collider, SpatialHash};
+use stats::{RunStats, RunStatsPlugin};
+use waves::{run_spawn_director, SpawnDirector, WavesPlugin};
+
+/// Despawned when a new run starts.
+#[derive(Component, Debug, Clone)]
+pub struct RemoveOnRespawn;
+
+#[derive(Component, Debug, Clone)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+#[derive(Component, Debug, Clone)]
+pub struct Cooldown {
+    pub start_time: f32,
+    pub duration: f32,
+}
+impl Cooldown {
+    fn is_ready(&self, elapsed_seconds: f32) -> bool {
+        self.start_time + self.duration < elapsed_seconds
+    }
+}
+
+/// Index in [`Teams`]: the AIs are all in one team, each player has their own.
+#[derive(Component, Clone)]
+pub struct TeamIdx(pub usize);
+
+impl TeamIdx {
+    pub const ENEMIES: TeamIdx = TeamIdx(1);
+
+    /// The first player keeps the first team, the next ones come after the enemies.
+    pub fn player(index: usize) -> Self {
+        match index {
+            0 => TeamIdx(0),
+            _ => TeamIdx(index + 1),
+        }
+    }
+
+    pub fn is_enemies(&self) -> bool {
+        self.0 == Self::ENEMIES.0
+    }
+}
+
+#[derive(Resource)]
+pub struct Teams {
+    pub colors: Vec<(Color, Color)>,
+}
+
+impl Default for Teams {
+    fn default() -> Self {
+        Self {
+            colors: vec![
+                (Color::WHITE * 5f32, Color::GREEN * 5f32),
+                (Color::ORANGE * 5f32, Color::RED * 5f32),
+                (Color::CYAN * 5f32, Color::BLUE * 5f32),
+                (Color::YELLOW * 5f32, Color::GOLD * 5f32),
+                (Color::PINK * 5f32, Color::PURPLE * 5f32),
+            ],
+        }
+    }
+}
+
+/// Rules a run is played with, high scores are kept separately for each.
+#[derive(Resource, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameMode {
+    #[default]
+    Solo,
+    /// Local players on the same screen, see [`actions::LocalPlayers`] for their devices.
+    Coop { players: u8, lives: Lives },
+}
+
+impl GameMode {
+    pub const MAX_PLAYERS: usize = 4;
+
+    pub fn players(&self) -> usize {
+        match *self {
+            GameMode::Solo => 1,
+            GameMode::Coop { players, .. } => players as usize,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lives {
+    /// One pool for the whole team, any player can use it.
+    Shared,
+    /// Each player has their own lives, and watches the others once they are out.
+    Split,
+}
+
+/// Respawns left in the current run, none in solo.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub enum RemainingLives {
+    Shared(u32),
+    /// By [`Player`] index.
+    Split(Vec<u32>),
+}
+
+impl Default for RemainingLives {
+    fn default() -> Self {
+        Self::Shared(0)
+    }
+}
+
+impl RemainingLives {
+    /// `lives` counts the first one of each player.
+    pub fn new(mode: GameMode, lives: u32) -> Self {
+        let respawns = lives.saturating_sub(1);
+        match mode {
+            GameMode::Solo => Self::Shared(0),
+            GameMode::Coop {
+                players,
+                lives: Lives::Shared,
+            } => Self::Shared(respawns * players as u32),
+            GameMode::Coop {
+                players,
+                lives: Lives::Split,
+            } => Self::Split(vec![respawns; players as usize]),
+        }
+    }
+
+    /// Uses one of the lives available to the player, if any is left.
+    pub fn take(&mut self, player: usize) -> bool {
+        let left = match self {
+            Self::Shared(left) => left,
+            Self::Split(lives) => match lives.get_mut(player) {
+                Some(left) => left,
+                None => return false,
+            },
+        };
+        if *left == 0 {
+            return false;
+        }
+        *left -= 1;
+        true
+    }
+}
+
+/// Players waiting to respawn, with the simulation seconds when they will.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct PendingRespawns(pub Vec<(usize, f32)>);
+
+/// Where the players of the next run start instead of the spawns of the arena,
+/// set when play-testing from the editor.
+#[derive(Resource, Debug, Default)]
+pub struct PlayTestStart(pub Option<Vec2>);
+
+#[derive(Component, Clone)]
+pub struct HealthPickup(pub f32);
+
+/// Asks to remove `amount` health from `victim`, which may have been despawned in the meantime.
+#[derive(Event, Debug, Clone)]
+pub struct EventTryApplyDamages {
+    /// Entity which fired the bullet, it may not exist anymore.
+    pub attacker: Entity,
+    pub victim: Entity,
+    pub amount: f32,
+    /// First damages of the bullet, see [`RunStats::shots_hit`].
+    pub first_hit: bool,
+}
+
+/// Sent when damages were actually applied.
+#[derive(Event, Debug)]
+pub struct EventDamaged {
+    pub attacker: Entity,
+    pub victim: Entity,
+    pub amount: f32,
+    pub first_hit: bool,
+}
+
+/// Sent when the damages brought the health of `victim` to zero, it is despawned at the end of the tick.
+#[derive(Event, Debug)]
+pub struct EventDied {
+    pub attacker: Entity,
+    pub victim: Entity,
+    pub position: Vec2,
+}
+
+#[derive(Resource)]
+pub struct GameDef {
+    /// Seconds between two spawns when the endless phase starts, see [`waves`].
+    pub spawn_interval: f32,
+    pub spawn_interval_multiplier_per_second: f32,
+    /// The spawn interval never goes below this.
+    pub min_spawn_interval: f32,
+    /// Whether bullets hit the other members of the team which fired them,
+    /// or the other players when fired by a player.
+    pub friendly_fire: bool,
+    /// Lives of each player in co-op, the first one included.
+    pub coop_lives: u32,
+    /// Seconds between the death of a player and their respawn, when they have a life left.
+    pub respawn_delay: f32,
+}
+
+impl GameDef {
+    /// Seconds between two spawns, `elapsed` seconds into the endless phase.
+    pub fn spawn_interval_at(&self, elapsed: f32) -> f32 {
+        (self.spawn_interval * self.spawn_interval_multiplier_per_second.powf(elapsed))
+            .max(self.min_spawn_interval)
+    }
+
+    pub fn can_damage(&self, attacker: &TeamIdx, victim: &TeamIdx) -> bool {
+        self.friendly_fire || attacker.is_enemies() != victim.is_enemies()
+    }
+}
+
+impl Default for GameDef {
+    fn default() -> Self {
+        Self {
+            spawn_interval: 5f32,
+            spawn_interval_multiplier_per_second: 0.99f32,
+            min_spawn_interval: 0.5f32,
+            friendly_fire: false,
+            coop_lives: 3,
+            respawn_delay: 2f32,
+        }
+    }
+}
+
+/// Every gameplay system, only running while [`GameState::Playing`].
+///
+/// Its run conditions are evaluated once per tick, so a tick is either fully simulated or not at all.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct SimulationSet;
+
+/// Ordering of the gameplay systems within a `FixedUpdate` tick, all part of [`SimulationSet`].
+///
+/// Sets run one after the other. Systems inside a set are chained whenever they touch the same data,
+/// so their order never depends on thread scheduling and the simulation stays deterministic.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum GameSet {
+    /// Saves the state the tick starts from, see [`rollback`].
+    Snapshot,
+    Tick,
+    /// Reads the devices into [`PlayerInput`].
+    Input,
+    /// Records or overrides [`PlayerInput`], see [`replay`].
+    Replay,
+    Movement,
+    Damages,
+    Collisions,
+    Despawn,
+}
+
+/// The game rules, without any window, rendering, audio or UI.
+///
+/// Runs on top of `MinimalPlugins` and `AssetPlugin`, so it can be driven from tests and tools.
+/// It is deterministic: insert a [`GameRng`] with a chosen seed beforehand to replay the same game.
+/// Add [`presentation::PresentationPlugin`] on top of it to actually play the game.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>();
+        app.add_plugins((EnemyArchetypesPlugin, WavesPlugin, ArenaPlugin));
+        app.add_plugins(DespawnAfterPlugin);
+        app.add_plugins(ReplayPlugin);
+        app.add_plugins(RunStatsPlugin);
+        app.add_plugins((RollbackPlugin, NetworkPlugin));
+        app.init_resource::<GameDef>();
+        app.init_resource::<GameMode>();
+        app.init_resource::<RemainingLives>();
+        app.init_resource::<PendingRespawns>();
+        app.init_resource::<PlayTestStart>();
+        app.init_resource::<Teams>();
+        app.init_resource::<GameRng>();
+        app.init_resource::<SimulationTime>();
+        app.init_resource::<TimeScale>();
+        app.init_resource::<SpatialHash>();
+        app.init_resource::<FlowField>();
+        app.insert_resource(FixedTime::new_from_secs(SIMULATION_TIMESTEP));
+        app.add_systems(PreUpdate, apply_time_scale);
+        app.add_simulation_event::<EventBulletSpawn>();
+        app.add_simulation_event::<EventTryApplyDamages>();
+        app.add_simulation_event::<EventDamaged>();
+        app.add_simulation_event::<EventDied>();
+        app.configure_set(
+            FixedUpdate,
+            SimulationSet
+                .run_if(in_state(GameState::Playing))
+                .run_if(not(is_leaving_state)),
+        );
+        for from in RUN_STARTING_STATES {
+            app.add_systems(
+                OnTransition {
+                    from,
+                    to: GameState::Playing,
+                },
+                start_run,
+            );
+        }
+        app.configure_sets(
+            FixedUpdate,
+            (
+                GameSet::Snapshot,
+                GameSet::Tick,
+                GameSet::Input,
+                GameSet::Replay,
+                GameSet::Movement,
+                GameSet::Damages,
+                GameSet::Collisions,
+                GameSet::Despawn,
+            )
+                .chain()
+                .in_set(SimulationSet),
+        );
+        app.add_systems(
+            FixedUpdate,
+            (
+                advance_simulation_time.in_set(GameSet::Tick),
+                (
+                    player_input_movement,
+                    player_fire,
+                    run_spawn_director,
+                    arena::run_pickup_spawners,
+                    // Also rebuilt for the collisions, once everything moved.
+                    rebuild_spatial_hash,
+                    behaviour::ai_think,
+                    navigation::update_flow_field,
+                    steering::steer_ais,
+                    behaviour::ai_fire,
+                    boss::boss_phases,
+                    boss::boss_fire,
+                    bullets_homing,
+                    move_targets,
+                    move_direction,
+                    bullets_walls,
+                )
+                    .chain()
+                    .in_set(GameSet::Movement),
+                (try_apply_damages, handle_player_deaths, respawn_players)
+                    .chain()
+                    .in_set(GameSet::Damages),
+                (
+                    rebuild_spatial_hash,
+                    collisions_player_pickups,
+                    collisions_bullet_health,
+                )
+                    .chain()
+                    .in_set(GameSet::Collisions),
+            ),
+        );
+    }
+}
+
+pub trait SimulationEventAppExt {
+    /// Like `add_event`, but the events are kept for two ticks instead of two frames,
+    /// otherwise a frame without any tick would drop them before the next tick reads them.
+    fn add_simulation_event<T: Event>(&mut self) -> &mut Self;
+}
+
+impl SimulationEventAppExt for App {
+    fn add_simulation_event<T: Event>(&mut self) -> &mut Self {
+        if !self.world.contains_resource::<Events<T>>() {
+            self.init_resource::<Events<T>>().add_systems(
+                FixedUpdate,
+                Events::<T>::update_system.in_set(GameSet::Tick),
+            );
+        }
+        self
+    }
+}
+
+/// Clears what is left of the previous run, moves to the selected arena and spawns the players.
+/// Going from these states to [`GameState::Playing`] starts a new run.
+pub const RUN_STARTING_STATES: [GameState; 3] =
+    [GameState::MainMenu, GameState::GameOver, GameState::Editor];
+
+fn start_run(
+    mut commands: Commands,
+    q_leftovers: Query<Entity, Or<(With<Player>, With<RemoveOnRespawn>)>>,
+    game_def: Res<GameDef>,
+    selected_arena: Res<SelectedArena>,
+    arena_assets: Res<ArenaAssets>,
+    arenas: Res<Assets<Arena>>,
+    mut arena: ResMut<Arena>,
+    mode: Res<GameMode>,
+    mut director: ResMut<SpawnDirector>,
+    mut stats: ResMut<RunStats>,
+    mut lives: ResMut<RemainingLives>,
+    mut respawns: ResMut<PendingRespawns>,
+    mut rng: ResMut<GameRng>,
+    mut play_test_start: ResMut<PlayTestStart>,
+) {
+    // Every run of a seed is the same game, so their high scores can be compared.
+    *rng = GameRng::from_seed(rng.seed());
+    *director = SpawnDirector::default();
+    *stats = RunStats::default();
+    *lives = RemainingLives::new(*mode, game_def.coop_lives);
+    respawns.0.clear();
+    for e in q_leftovers.iter() {
+        commands.entity(e).despawn();
+    }
+    if let Some(selected) = arena_assets.get(&selected_arena.0, &arenas) {
+        // Only replaced when it changes, so the music doesn't start over.
+        if *arena != *selected {
+            *arena = selected.clone();
+        }
+    }
+    // Only the first run of a play-test, the next ones start from the spawns.
+    let start = play_test_start.0.take();
+    for index in 0..mode.players() {
+        let spawn = match start {
+            // Keeps the players as far apart as at the spawns.
+            Some(start) => start + arena.player_spawn(index) - arena.player_spawn(0),
+            None => arena.player_spawn(index),
+        };
+        spawn_player(&mut commands, index, spawn);
+    }
+    for spawner in arena.pickup_spawners.iter() {
+        commands.spawn((
+            PickupSpawner {
+                next_in: spawner.interval,
+                ..spawner.clone()
+            },
+            RemoveOnRespawn,
+        ));
+    }
+}
+
+/// The simulation asked for another state, the remaining ticks of the frame must not run
+/// as the state only changes once per frame.
+fn is_leaving_state(next_state: Res<NextState<GameState>>) -> bool {
+    next_state.0.is_some()
+}
+
+/// Queues the respawn of the dead players with a life left, the run ends once nobody is left.
+pub fn handle_player_deaths(
+    time: Res<SimulationTime>,
+    game_def: Res<GameDef>,
+    mut events_died: EventReader<EventDied>,
+    q_players: Query<&Player>,
+    mut lives: ResMut<RemainingLives>,
+    mut respawns: ResMut<PendingRespawns>,
+    mut stats: ResMut<RunStats>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let mut deaths = 0;
+    for ev in events_died.iter() {
+        let Ok(player) = q_players.get(ev.victim) else {
+            continue;
+        };
+        deaths += 1;
+        if lives.take(player.0) {
+            respawns
+                .0
+                .push((player.0, time.elapsed_seconds() + game_def.respawn_delay));
+        }
+    }
+    // Despawns are deferred to the end of the tick, the dead players are still counted.
+    if deaths > 0 && q_players.iter().count() == deaths && respawns.0.is_empty() {
+        stats.finished = true;
+        game_state.set(GameState::GameOver);
+    }
+}
+
+fn respawn_players(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    arena: Res<Arena>,
+    mut respawns: ResMut<PendingRespawns>,
+) {
+    let elapsed_seconds = time.elapsed_seconds();
+    respawns.0.retain(|&(index, respawn_at)| {
+        if respawn_at > elapsed_seconds {
+            return true;
+        }
+        spawn_player(&mut commands, index, arena.player_spawn(index));
+        false
+    });
+}
+
+pub fn collisions_bullet_health(
+    mut commands: Commands,
+    game_def: Res<GameDef>,
+    spatial_hash: Res<SpatialHash>,
+    mut events_try_damage: EventWriter<EventTryApplyDamages>,
+    mut q_bullets: Query<(
+        Entity,
+        &Transform,
+        &Collider,
+        &BulletOwner,
+        &TeamIdx,
+        &mut Bullet,
+    )>,
+    q_health: Query<&TeamIdx, With<Health>>,
+) {
+    for (e_bullet, bullet_position, collider, bullet_owner, team, mut bullet) in
+        q_bullets.iter_mut()
+    {
+        let attacker = bullet_owner.entity;
+        // Bullets go through the entities they can't damage.
+        let can_damage = |victim: Entity| {
+            attacker != victim
+                && q_health
+                    .get(victim)
+                    .is_ok_and(|victim_team| game_def.can_damage(team, victim_team))
+        };
+        let position = bullet_position.translation.xy();
+        for entry in spatial_hash.query(position, collider.radius) {
+            let victim = entry.entity;
+            if bullet.hits.contains(&victim) || !can_damage(victim) {
+                continue;
+            }
+            match bullet.behaviour {
+                BulletBehaviour::Exploding { radius } => {
+                    for caught in spatial_hash.query(position, radius) {
+                        if can_damage(caught.entity) {
+                            events_try_damage.send(EventTryApplyDamages {
+                                attacker,
+                                victim: caught.entity,
+                                amount: bullet.damage,
+                                first_hit: !std::mem::replace(&mut bullet.has_hit, true),
+                            });
+                        }
+                    }
+                    commands.entity(e_bullet).despawn();
+                }
+                BulletBehaviour::Piercing { hits } => {
+                    events_try_damage.send(EventTryApplyDamages {
+                        attacker,
+                        victim,
+                        amount: bullet.damage,
+                        first_hit: !std::mem::replace(&mut bullet.has_hit, true),
+                    });
+                    bullet.hits.push(victim);
+                    if bullet.hits.len() < hits as usize {
+                        continue;
+                    }
+                    commands.entity(e_bullet).despawn();
+                }
+                _ => {
+                    events_try_damage.send(EventTryApplyDamages {
+                        attacker,
+                        victim,
+                        amount: bullet.damage,
+                        first_hit: !std::mem::replace(&mut bullet.has_hit, true),
+                    });
+                    commands.entity(e_bullet).despawn();
+                }
+            }
+            break;
+        }
+    }
+}
+pub fn collisions_player_pickups(
+    mut commands: Commands,
+    spatial_hash: Res<SpatialHash>,
+    q_pickups: Query<&HealthPickup>,
+    mut q_health: Query<(&Transform, &Collider, &mut Health), Without<HealthPickup>>,
+) {
+    // Despawned at the end of the tick, a pickup overlapped by several entities only heals one.
+    let mut consumed = HashSet::new();
+    for (t, collider, mut health) in q_health.iter_mut() {
+        for entry in spatial_hash.query(t.translation.xy(), collider.radius) {
+            let Ok(pickup) = q_pickups.get(entry.entity) else {
+                continue;
+            };
+            if !consumed.insert(entry.entity) {
+                continue;
+            }
+            health.current += pickup.0;
+            health.current = health.current.min(health.max);
+            commands.entity(entry.entity).despawn();
+        }
+    }
+}
+
+pub fn spawn_pickup(commands: &mut Commands, amount: f32, position: Vec2) {
+    commands.spawn((
+        HealthPickup(amount),
+        Transform::from_translation(position.extend(2f32)),
+        Collider { radius: 2f32 },
+        RemoveOnRespawn,
+    ));
+}
+
+pub fn try_apply_damages(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    mut events_try_damage: EventReader<EventTryApplyDamages>,
+    mut events_damaged: EventWriter<EventDamaged>,
+    mut events_died: EventWriter<EventDied>,
+    mut q_health: Query<(&Transform, &mut Health, Option<&DropTable>)>,
+) {
+    for ev in events_try_damage.iter() {
+        // The victim may have been despawned, or killed by an earlier event of this tick.
+        let Ok((transform, mut health, drop_table)) = q_health.get_mut(ev.victim) else {
+            continue;
+        };
+        if health.current <= 0f32 {
+            continue;
+        }
+        health.current -= ev.amount;
+        events_damaged.send(EventDamaged {
+            attacker: ev.attacker,
+            victim: ev.victim,
+            amount: ev.amount,
+            first_hit: ev.first_hit,
+        });
+        if health.current <= 0f32 {
+            commands.entity(ev.victim).despawn();
+            events_died.send(EventDied {
+                attacker: ev.attacker,
+                victim: ev.victim,
+                position: transform.translation.xy(),
+            });
+            for drop in drop_table.iter().flat_map(|table| table.0.iter()) {
+                if rng.rng.gen::<f32>() >= drop.chance {
+                    continue;
+                }
+                match drop.pickup {
+                    Pickup::Health(amount) => {
+                        spawn_pickup(&mut commands, amount, transform.translation.xy())
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,272 +1,129 @@
-#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+use std::{net::SocketAddr, str::FromStr};
 
-pub mod ai;
-mod bullets;
-pub mod despawn_after;
-pub mod draw;
-pub mod menu;
-pub mod movement;
-pub mod player;
-pub mod utils;
+use bevy::prelude::*;
 
-use bevy::{
-    core_pipeline::{bloom::BloomSettings, tonemapping::Tonemapping},
-    math::vec2,
-    prelude::*,
-    render::camera::ScalingMode,
+use circles_madness::{
+    network::OnlineSession,
+    presentation::PresentationPlugin,
+    replay::{Replay, ReplayMode},
+    rng::GameRng,
+    SimulationPlugin,
 };
-use bevy_vector_shapes::prelude::*;
 
-use ai::*;
-use bullets::*;
-use despawn_after::*;
-use draw::*;
-use menu::*;
-use movement::*;
-use player::*;
-
-fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                fit_canvas_to_parent: true,
-                ..default()
-            }),
-            ..default()
-        }))
-        .add_plugins(DespawnAfterPlugin)
-        .add_plugins(Game)
-        .run();
-}
-#[derive(Component, Debug)]
-pub struct RemoveOnRespawn;
-
-#[derive(Component, Debug)]
-pub struct Health {
-    pub current: f32,
-    pub max: f32,
+const USAGE: &str = "Usage: circles_madness [--seed <seed>] \
+    [--record <file> | --replay <file> | --online <port> <peer address> <player>]";
+
+/// The command line, see [`USAGE`].
+#[derive(Default)]
+struct Args {
+    /// `--seed <seed>` plays with this seed instead of a random one, to come back to its high scores.
+    seed: Option<u64>,
+    /// `--record <file>` saves the game inputs to a replay file.
+    record: Option<String>,
+    /// `--replay <file>` plays a replay file back, with its own seed.
+    replay: Option<String>,
+    /// `--online <port> <peer address> <player>` plays online with the peer, as player 0 or 1.
+    online: Option<(u16, SocketAddr, usize)>,
 }
-#[derive(Component, Debug)]
-pub struct Cooldown {
-    pub start_time: f32,
-    pub duration: f32,
-}
-impl Cooldown {
-    fn is_ready(&self, elapsed_seconds: f32) -> bool {
-        self.start_time + self.duration < elapsed_seconds
-    }
-}
-
-pub struct Game;
 
-#[derive(Component, Clone)]
-pub struct TeamIdx(pub usize);
-
-#[derive(Resource)]
-pub struct Teams {
-    pub colors: Vec<(Color, Color)>,
-}
-
-impl Default for Teams {
-    fn default() -> Self {
-        Self {
-            colors: vec![
-                (Color::WHITE * 5f32, Color::GREEN * 5f32),
-                (Color::ORANGE * 5f32, Color::RED * 5f32),
-            ],
+impl Args {
+    /// Fails on unknown, repeated or conflicting flags, rather than ignoring some of them.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let mut parsed = Self::default();
+        while let Some(flag) = args.next() {
+            let repeated = match flag.as_str() {
+                "--seed" => parsed
+                    .seed
+                    .replace(value(&mut args, &flag, "seed")?)
+                    .is_some(),
+                "--record" => parsed
+                    .record
+                    .replace(value(&mut args, &flag, "file")?)
+                    .is_some(),
+                "--replay" => parsed
+                    .replay
+                    .replace(value(&mut args, &flag, "file")?)
+                    .is_some(),
+                "--online" => {
+                    let online = (
+                        value(&mut args, &flag, "port")?,
+                        value(&mut args, &flag, "peer address")?,
+                        value(&mut args, &flag, "player")?,
+                    );
+                    if online.2 > 1 {
+                        return Err(format!("{flag} is for player 0 or 1"));
+                    }
+                    parsed.online.replace(online).is_some()
+                }
+                _ => return Err(format!("Unknown argument {flag}")),
+            };
+            if repeated {
+                return Err(format!("{flag} is given twice"));
+            }
         }
-    }
-}
-
-#[derive(Component, Clone)]
-pub struct HealthPickup(pub f32);
-
-#[derive(Event)]
-pub struct EventTryApplyDamages(pub Entity, pub f32);
-
-#[derive(Resource)]
-pub struct GameDef {
-    pub spawn_interval: f32,
-    pub spawn_interval_multiplier_per_second: f32,
-}
-
-impl Default for GameDef {
-    fn default() -> Self {
-        Self {
-            spawn_interval: 5f32,
-            spawn_interval_multiplier_per_second: 0.9f32,
+        let modes = [
+            parsed.record.is_some(),
+            parsed.replay.is_some(),
+            parsed.online.is_some(),
+        ];
+        if modes.into_iter().filter(|mode| *mode).count() > 1 {
+            return Err("Only one of --record, --replay and --online can be given".to_string());
+        }
+        if parsed.seed.is_some() && parsed.replay.is_some() {
+            return Err(
+                "--seed can't be given with --replay, which plays its own seed".to_string(),
+            );
         }
+        Ok(parsed)
     }
 }
 
-impl Plugin for Game {
-    fn build(&self, app: &mut App) {
-        app.add_plugins(Shape2dPlugin::default());
-        app.add_plugins(BulletPlugin);
-        app.add_plugins(MenuPlugin);
-        app.init_resource::<GameDef>();
-        app.init_resource::<Teams>();
-        app.add_event::<EventBulletSpawn>();
-        app.add_event::<EventTryApplyDamages>();
-        app.add_systems(Startup, setup);
-        app.add_systems(
-            Update,
-            (
-                (player_respawn),
-                (
-                    /*handle_mouse_to_move, */ handle_clicks_to_fire,
-                    wasd_movement,
-                ),
-                (
-                    move_targets,
-                    move_direction,
-                    spawn_ais,
-                    ai::ai_fire,
-                    ai::ai_move,
-                ),
-                (try_apply_damages,),
-                (
-                    collisions_player_pickups,
-                    collisions_bullet_health,
-                    draw,
-                    draw_bullets,
-                    draw_health,
-                    draw_cooldown,
-                    draw_pickups,
-                ),
-            )
-                .chain()
-                .run_if(in_state(GameState::Playing)),
-        );
-    }
+/// The `name` value given to `flag`, next in `args`.
+fn value<T: FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+    name: &str,
+) -> Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{flag} is missing <{name}>"))?;
+    value
+        .parse()
+        .map_err(|_| format!("{flag} has an invalid <{name}>: {value}"))
 }
 
-fn player_respawn(
-    mut commands: Commands,
-    mut q: ParamSet<(
-        Query<Entity, With<Player>>,
-        Query<Entity, With<RemoveOnRespawn>>,
-    )>,
-    mut game_state: ResMut<NextState<GameState>>,
-) {
-    if q.p0().iter().next().is_some() {
-        return;
+fn main() {
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{error}\n{USAGE}");
+        std::process::exit(2);
+    });
+    let mut app = App::new();
+    if let Some(seed) = args.seed {
+        app.insert_resource(GameRng::from_seed(seed));
     }
-    // Remove extra stuff
-    for e in q.p1().iter() {
-        commands.entity(e).despawn();
+    if let Some((port, peer, player)) = args.online {
+        match OnlineSession::bind(port, peer, player) {
+            Ok(session) => app.insert_resource(session),
+            Err(error) => panic!("Couldn't listen on port {port}: {error}"),
+        };
     }
-    // Spawn player
-    commands.spawn((
-        Transform {
-            translation: Vec2::ZERO.extend(2f32),
-            ..default()
-        },
-        MoveSpeed(130f32),
-        MoveDirection(Vec2::ZERO),
-        MoveTarget {
-            target: Some(Vec2::new(0f32, 0f32)),
-        },
-        Health {
-            current: 1f32,
-            max: 1f32,
-        },
-        Cooldown {
-            start_time: 0.0,
-            duration: 0.5,
+    let replay_mode = match (args.record, args.replay) {
+        (Some(path), _) => ReplayMode::record(Some(path.into())),
+        (_, Some(path)) => match Replay::load(&path) {
+            Ok(replay) => ReplayMode::playback(replay),
+            Err(error) => panic!("Couldn't load replay {path}: {error:?}"),
         },
-        Player,
-        TeamIdx(0),
-    ));
-    // Go back to menu
-    // This system is called at the begining of the game and triggers the menu,
-    // The game should be started in the Playing state to avoid having a double menu
-    // until this is somehow fixed
-    game_state.0 = Some(GameState::Menu);
-}
-
-pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn((
-        Camera2dBundle {
-            projection: OrthographicProjection {
-                scaling_mode: ScalingMode::AutoMin {
-                    min_width: 512.0,
-                    min_height: 512.0,
-                },
-                ..default()
-            },
-            camera: Camera {
-                hdr: true, // 1. HDR is required for bloom
+        _ => ReplayMode::Off,
+    };
+    app.insert_resource(replay_mode)
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                fit_canvas_to_parent: true,
                 ..default()
-            },
-            tonemapping: Tonemapping::TonyMcMapface, // 2. Using a tonemapper that desaturates to white is recommended
-            ..default()
-        },
-        BloomSettings::default(), // 3. Enable bloom for the camera
-    ));
-
-    commands.spawn(SpriteBundle {
-        texture: asset_server.load("bg.jpg"),
-        transform: Transform::from_xyz(0.0, 20.0, 0.0),
-        sprite: Sprite {
-            custom_size: Some(vec2(2048.0 * 1.5 / 2.0, 2048.0 / 2.0)),
+            }),
             ..default()
-        },
-        ..default()
-    });
-}
-
-pub fn collisions_bullet_health(
-    mut commands: Commands,
-    mut events_try_damage: EventWriter<EventTryApplyDamages>,
-    q_bullets: Query<(Entity, &Transform, &BulletOwner)>,
-    q_health: Query<(Entity, &Transform, &Health)>,
-) {
-    for (e_bullet, bullet_position, bullet_owner) in q_bullets.iter() {
-        for (e, t, _) in q_health.iter() {
-            if bullet_owner.entity != e
-                && bullet_position.translation.distance(t.translation) < 20f32
-            {
-                commands.entity(e_bullet).despawn();
-                events_try_damage.send(EventTryApplyDamages(e, 0.25f32));
-                continue;
-            }
-        }
-    }
-}
-pub fn collisions_player_pickups(
-    mut commands: Commands,
-    q_pickups: Query<(Entity, &Transform, &HealthPickup)>,
-    mut q_health: Query<(Entity, &Transform, &mut Health), Without<HealthPickup>>,
-) {
-    for (e, t, mut health) in q_health.iter_mut() {
-        for (e_pickup, bullet_position, pickup) in q_pickups.iter() {
-            if bullet_position.translation.distance(t.translation) < 20f32 {
-                health.current += pickup.0;
-                health.current = health.current.min(health.max);
-                commands.entity(e_pickup).despawn();
-                continue;
-            }
-        }
-    }
-}
-
-pub fn try_apply_damages(
-    mut commands: Commands,
-    mut events_try_damage: EventReader<EventTryApplyDamages>,
-    mut q_health: Query<(Entity, &Transform, &mut Health)>,
-) {
-    for ev in events_try_damage.iter() {
-        let (e, transform, mut health) = q_health.get_mut(ev.0).unwrap();
-        health.current -= 0.25f32;
-        // TODO: fire event touched to spawn particles!
-        if dbg!(health.current) <= 0f32 {
-            commands.entity(e).despawn();
-            commands.spawn((
-                HealthPickup(0.1f32),
-                Transform::from_translation(transform.translation),
-                RemoveOnRespawn,
-            ));
-        }
-    }
+        }))
+        .add_plugins((SimulationPlugin, PresentationPlugin))
+        .run();
 }
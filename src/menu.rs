@@ -1,20 +1,46 @@
 use bevy::{prelude::*, time::Stopwatch};
 
+use crate::{
+    actions::{Action, ActionState, Bindings},
+    arena::{Arena, ArenaAssets, SelectedArena},
+    arena_generator::regenerate_arena,
+    highscores::{enter_name, is_entering_name, HighScores, NameEntry},
+    network::OnlineSession,
+    replay::ReplayMode,
+    rng::GameRng,
+    simulation_time::{advance_simulation_time, SimulationTime},
+    stats::RunStats,
+    GameMode, GameSet, Lives,
+};
+
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<GameState>()
-            .init_resource::<LastActivity>()
+        app.init_resource::<LastActivity>()
+            .init_resource::<MainMenuSelection>()
             .add_systems(Startup, (setup_controls_hint, setup_menu))
-            .add_systems(OnEnter(GameState::Menu), display_menu)
-            .add_systems(OnExit(GameState::Menu), hide_menu)
+            .add_systems(
+                FixedUpdate,
+                tick_last_activity
+                    .after(advance_simulation_time)
+                    .in_set(GameSet::Tick),
+            )
             .add_systems(
                 Update,
                 (
                     display_controls_hint,
-                    resume_game.run_if(in_state(GameState::Menu)),
+                    display_menu,
+                    display_high_scores,
+                    main_menu_actions.run_if(in_state(GameState::MainMenu)),
                     pause_game.run_if(in_state(GameState::Playing)),
+                    resume_or_quit
+                        .run_if(
+                            in_state(GameState::Paused).or_else(
+                                in_state(GameState::GameOver).and_then(not(is_entering_name)),
+                            ),
+                        )
+                        .after(enter_name),
                 ),
             );
     }
@@ -22,27 +48,55 @@ impl Plugin for MenuPlugin {
 
 #[derive(Default, Debug, Hash, PartialEq, Eq, Clone, States)]
 pub enum GameState {
-    Menu,
+    /// Waits for the assets the simulation needs, then goes to `MainMenu`.
     #[default]
+    Loading,
+    MainMenu,
+    /// Entered from `MainMenu`, `GameOver` or `Editor`, a new run starts, see [`crate::SimulationPlugin`].
     Playing,
+    /// The run is kept as is until resumed.
+    Paused,
+    /// The player died, the run stays visible behind its stats.
+    GameOver,
+    /// Rebinding screen, see [`crate::controls_menu`].
+    Controls,
+    /// Arena editing, see [`crate::editor`].
+    Editor,
 }
 
+/// Time played since the player last moved or fired, the controls are shown again after a while.
 #[derive(Resource, Default)]
 pub struct LastActivity(pub Stopwatch);
 
+impl LastActivity {
+    /// Seconds of inactivity before showing the controls.
+    pub const HINT_DELAY: f32 = 10f32;
+}
+
 #[derive(Component)]
 struct ControlsHint;
 
 #[derive(Component)]
 struct MenuNode;
 
+/// What the menu is about and its actions.
+#[derive(Component)]
+struct MenuTitle;
+
+/// Stats of the run which just ended, empty otherwise.
+#[derive(Component)]
+struct GameOverText;
+
+/// Best runs of the current mode, arena and seed, and the name being entered.
+#[derive(Component)]
+struct HighScoresText;
+
 fn setup_controls_hint(mut commands: Commands) {
     commands.spawn((
         TextBundle {
             text: Text {
                 sections: vec![TextSection {
-                    value: "WASD/Arrows: Move\nMouse/Click: Aim and shoot/Enter: Pause\n"
-                        .to_string(),
+                    value: String::new(),
                     style: TextStyle {
                         font_size: 30.,
                         ..default()
@@ -74,6 +128,7 @@ fn setup_menu(mut commands: Commands) {
                 style: Style {
                     width: Val::Percent(100.),
                     height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
                     align_content: AlignContent::Center,
                     ..default()
                 },
@@ -85,66 +140,357 @@ fn setup_menu(mut commands: Commands) {
         .id();
 
     let text_node = commands
-        .spawn((TextBundle {
-            text: Text {
-                sections: vec![TextSection {
-                    value: "Press ENTER to start/resume the game\n".to_string(),
-                    style: TextStyle {
-                        font_size: 50.,
-                        ..default()
-                    },
-                }],
+        .spawn((
+            TextBundle {
+                text: Text {
+                    sections: vec![TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 50.,
+                            ..default()
+                        },
+                    }],
+                    ..default()
+                },
+                style: Style { ..default() },
                 ..default()
             },
-            style: Style { ..default() },
-            ..default()
-        },))
+            MenuTitle,
+        ))
+        .id();
+
+    let game_over_node = commands
+        .spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 40.,
+                    ..default()
+                },
+            ),
+            GameOverText,
+        ))
+        .id();
+
+    let high_scores_node = commands
+        .spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 30.,
+                    ..default()
+                },
+            ),
+            HighScoresText,
+        ))
         .id();
 
-    commands.entity(menu_node).add_child(text_node);
+    commands
+        .entity(menu_node)
+        .push_children(&[text_node, game_over_node, high_scores_node]);
 }
 
 fn display_controls_hint(
-    mut controls_hint: Query<&mut Style, With<ControlsHint>>,
-    mut last_activity: ResMut<LastActivity>,
-    time: Res<Time>,
+    mut controls_hint: Query<(&mut Style, &mut Text), With<ControlsHint>>,
+    last_activity: Res<LastActivity>,
     game_state: Res<State<GameState>>,
+    bindings: Res<Bindings>,
 ) {
-    let mut controls_hint = controls_hint.single_mut();
-    if game_state.get() == &GameState::Menu {
-        controls_hint.display = Display::DEFAULT;
+    let (mut style, mut text) = controls_hint.single_mut();
+    if bindings.is_changed() {
+        text.sections[0].value = format!(
+            "{}/{}/{}/{}/Left stick: Move\nMouse/Right stick: Aim\n{}: Shoot\n{}: Dash\n{}: Pause\n",
+            bindings.labels(Action::MoveUp),
+            bindings.labels(Action::MoveLeft),
+            bindings.labels(Action::MoveDown),
+            bindings.labels(Action::MoveRight),
+            bindings.labels(Action::Fire),
+            bindings.labels(Action::Dash),
+            bindings.labels(Action::Pause),
+        );
+    }
+    style.display = if matches!(game_state.get(), GameState::Controls | GameState::Editor) {
+        Display::None
+    } else if game_state.get() != &GameState::Playing
+        || last_activity.0.elapsed_secs() > LastActivity::HINT_DELAY
+    {
+        Display::DEFAULT
     } else {
-        last_activity.0.tick(time.elapsed());
-        // dbg!(last_activity.0.elapsed_secs()); //Doesn't seem to be seconds? wtf
-        if last_activity.0.elapsed_secs() > 900. {
-            controls_hint.display = Display::DEFAULT;
-        } else {
-            controls_hint.display = Display::None;
+        Display::None
+    };
+}
+
+fn tick_last_activity(time: Res<SimulationTime>, mut last_activity: ResMut<LastActivity>) {
+    last_activity.0.tick(time.delta);
+}
+
+fn display_menu(
+    game_state: Res<State<GameState>>,
+    stats: Res<RunStats>,
+    bindings: Res<Bindings>,
+    selection: Res<MainMenuSelection>,
+    mode: Res<GameMode>,
+    selected_arena: Res<SelectedArena>,
+    arena_assets: Option<Res<ArenaAssets>>,
+    arenas: Res<Assets<Arena>>,
+    rng: Res<GameRng>,
+    // The seed shown, the rng itself changes at every tick.
+    mut shown_seed: Local<Option<u64>>,
+    mut q_menu: Query<&mut Style, With<MenuNode>>,
+    mut q_title: Query<&mut Text, (With<MenuTitle>, Without<GameOverText>)>,
+    mut q_game_over: Query<&mut Text, (With<GameOverText>, Without<MenuTitle>)>,
+) {
+    if shown_seed.replace(rng.seed()) == Some(rng.seed())
+        && !game_state.is_changed()
+        && !selection.is_changed()
+        && !mode.is_changed()
+        && !selected_arena.is_changed()
+    {
+        return;
+    }
+    let arena = arena_assets
+        .and_then(|assets| assets.get(&selected_arena.0, &arenas))
+        .map(|arena| arena.name.as_str())
+        .unwrap_or_default();
+    let confirm = bindings.labels(Action::Confirm);
+    let back = bindings.labels(Action::Back);
+    let title = match game_state.get() {
+        GameState::MainMenu => MainMenuEntry::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let marker = if i == selection.0 { ">" } else { " " };
+                format!("{marker} {}\n", entry.label(*mode, arena, rng.seed()))
+            })
+            .collect(),
+        GameState::Paused => format!("Paused\n{confirm}: resume, {back}: main menu\n"),
+        GameState::GameOver => format!("Game over\n{confirm}: retry, {back}: main menu\n"),
+        GameState::Loading | GameState::Playing | GameState::Controls | GameState::Editor => {
+            q_menu.single_mut().display = Display::None;
+            return;
         }
+    };
+    q_menu.single_mut().display = Display::DEFAULT;
+    q_title.single_mut().sections[0].value = title;
+    q_game_over.single_mut().sections[0].value = match game_state.get() {
+        GameState::GameOver => stats.summary(),
+        _ => String::new(),
+    };
+}
+
+/// Shown in the main menu and after a game over.
+fn display_high_scores(
+    game_state: Res<State<GameState>>,
+    mode: Res<GameMode>,
+    selected_arena: Res<SelectedArena>,
+    arena_assets: Option<Res<ArenaAssets>>,
+    arenas: Res<Assets<Arena>>,
+    rng: Res<GameRng>,
+    mut shown_seed: Local<Option<u64>>,
+    high_scores: Res<HighScores>,
+    name_entry: Res<NameEntry>,
+    mut q_text: Query<(&mut Text, &mut Style), With<HighScoresText>>,
+) {
+    if shown_seed.replace(rng.seed()) == Some(rng.seed())
+        && !game_state.is_changed()
+        && !high_scores.is_changed()
+        && !name_entry.is_changed()
+        && !selected_arena.is_changed()
+    {
+        return;
     }
+    let (mut text, mut style) = q_text.single_mut();
+    if !matches!(game_state.get(), GameState::MainMenu | GameState::GameOver) {
+        style.display = Display::None;
+        return;
+    }
+    style.display = Display::DEFAULT;
+    let arena = arena_assets
+        .and_then(|assets| assets.get(&selected_arena.0, &arenas))
+        .map(|arena| arena.name.as_str())
+        .unwrap_or_default();
+    let mut value = match &name_entry.0 {
+        Some(name) => format!("New high score! Enter your name: {name}_\n"),
+        None => String::new(),
+    };
+    value += &format!(
+        "High scores ({:?}, {}, seed {})\n",
+        *mode,
+        arena,
+        rng.seed()
+    );
+    for (rank, entry) in high_scores
+        .table(*mode, arena, rng.seed())
+        .iter()
+        .enumerate()
+    {
+        value += &format!(
+            "{}. {} - {} ({} kills, {:.0}s)\n",
+            rank + 1,
+            entry.name,
+            entry.score,
+            entry.kills,
+            entry.survival_time
+        );
+    }
+    text.sections[0].value = value;
 }
 
-fn display_menu(mut menu: Query<&mut Style, With<MenuNode>>) {
-    menu.single_mut().display = Display::DEFAULT;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MainMenuEntry {
+    Start,
+    /// Left and right change the number of local players.
+    Players,
+    /// Left and right switch between shared and split lives, in co-op.
+    Lives,
+    /// Left and right cycle through the arenas.
+    Arena,
+    /// Left and right change the seed of the next runs, and of the generated arena.
+    Seed,
+    /// Edits the selected arena.
+    Editor,
+    Controls,
 }
 
-fn hide_menu(mut menu: Query<&mut Style, With<MenuNode>>) {
-    menu.single_mut().display = Display::None;
+impl MainMenuEntry {
+    const ALL: [MainMenuEntry; 7] = [
+        MainMenuEntry::Start,
+        MainMenuEntry::Players,
+        MainMenuEntry::Lives,
+        MainMenuEntry::Arena,
+        MainMenuEntry::Seed,
+        MainMenuEntry::Editor,
+        MainMenuEntry::Controls,
+    ];
+
+    fn label(&self, mode: GameMode, arena: &str, seed: u64) -> String {
+        match (self, mode) {
+            (MainMenuEntry::Start, _) => "Start".to_string(),
+            (MainMenuEntry::Players, _) => format!("Players: < {} >", mode.players()),
+            (MainMenuEntry::Lives, GameMode::Solo) => "Lives: 1".to_string(),
+            (MainMenuEntry::Lives, GameMode::Coop { lives, .. }) => {
+                format!("Lives: < {lives:?} >")
+            }
+            (MainMenuEntry::Arena, _) => format!("Arena: < {arena} >"),
+            (MainMenuEntry::Seed, _) => format!("Seed: < {seed} >"),
+            (MainMenuEntry::Editor, _) => "Arena editor".to_string(),
+            (MainMenuEntry::Controls, _) => "Controls".to_string(),
+        }
+    }
+}
+
+/// Index of the highlighted entry of [`MainMenuEntry::ALL`].
+#[derive(Resource, Debug, Default)]
+struct MainMenuSelection(usize);
+
+/// The mode with `players` local players, keeping the lives setting when staying in co-op.
+fn with_players(mode: GameMode, players: usize) -> GameMode {
+    let lives = match mode {
+        GameMode::Solo => Lives::Shared,
+        GameMode::Coop { lives, .. } => lives,
+    };
+    match players {
+        0 | 1 => GameMode::Solo,
+        _ => GameMode::Coop {
+            players: players.min(GameMode::MAX_PLAYERS) as u8,
+            lives,
+        },
+    }
 }
 
-fn resume_game(
-    keyboard_input: Res<Input<KeyCode>>,
+fn main_menu_actions(
+    action_state: Res<ActionState>,
+    replay_mode: Res<ReplayMode>,
+    session: Option<Res<OnlineSession>>,
+    mut selection: ResMut<MainMenuSelection>,
+    mut mode: ResMut<GameMode>,
+    arena_assets: Res<ArenaAssets>,
+    mut arenas: ResMut<Assets<Arena>>,
+    mut selected_arena: ResMut<SelectedArena>,
+    mut rng: ResMut<GameRng>,
     mut game_state: ResMut<NextState<GameState>>,
     mut last_activity: ResMut<LastActivity>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::Return) {
-        game_state.0 = Some(GameState::Playing);
-        last_activity.0.reset();
+    if action_state.just_pressed(Action::MoveUp) {
+        selection.0 = selection.0.saturating_sub(1);
+    }
+    if action_state.just_pressed(Action::MoveDown) {
+        selection.0 = (selection.0 + 1).min(MainMenuEntry::ALL.len() - 1);
+    }
+    let step = match (
+        action_state.just_pressed(Action::MoveLeft),
+        action_state.just_pressed(Action::MoveRight) || action_state.just_pressed(Action::Confirm),
+    ) {
+        (true, false) => -1,
+        (false, true) => 1,
+        _ => 0,
+    };
+    // A replay is tied to the mode, arena and seed it was recorded with,
+    // an online session to its two players in the first arena and the seed of player 0.
+    let mode_locked = !matches!(*replay_mode, ReplayMode::Off) || session.is_some();
+    match MainMenuEntry::ALL[selection.0] {
+        MainMenuEntry::Start if action_state.just_pressed(Action::Confirm) => {
+            game_state.set(GameState::Playing);
+            last_activity.0.reset();
+        }
+        MainMenuEntry::Arena if step != 0 && !mode_locked => {
+            let names = arena_assets.names(&arenas);
+            let current = names
+                .iter()
+                .position(|name| *name == selected_arena.0)
+                .unwrap_or(0) as isize;
+            if let Some(name) =
+                names.get((current + step).rem_euclid(names.len().max(1) as isize) as usize)
+            {
+                selected_arena.0 = name.clone();
+            }
+        }
+        MainMenuEntry::Seed if step != 0 && !mode_locked => {
+            *rng = GameRng::from_seed(rng.seed().wrapping_add_signed(step as i64));
+            regenerate_arena(rng.seed(), &arena_assets, &mut arenas);
+        }
+        MainMenuEntry::Editor if action_state.just_pressed(Action::Confirm) && !mode_locked => {
+            game_state.set(GameState::Editor);
+        }
+        MainMenuEntry::Controls if action_state.just_pressed(Action::Confirm) => {
+            game_state.set(GameState::Controls);
+        }
+        MainMenuEntry::Players if step != 0 && !mode_locked => {
+            let players = (mode.players() as isize - 1 + step)
+                .rem_euclid(GameMode::MAX_PLAYERS as isize) as usize
+                + 1;
+            *mode = with_players(*mode, players);
+        }
+        MainMenuEntry::Lives if step != 0 && !mode_locked => {
+            if let GameMode::Coop { lives, .. } = &mut *mode {
+                *lives = match lives {
+                    Lives::Shared => Lives::Split,
+                    Lives::Split => Lives::Shared,
+                };
+            }
+        }
+        _ => {}
+    }
+}
+
+fn pause_game(action_state: Res<ActionState>, mut game_state: ResMut<NextState<GameState>>) {
+    if action_state.just_pressed(Action::Pause) {
+        game_state.set(GameState::Paused);
     }
 }
 
-fn pause_game(keyboard_input: Res<Input<KeyCode>>, mut game_state: ResMut<NextState<GameState>>) {
-    if keyboard_input.just_pressed(KeyCode::Return) {
-        game_state.0 = Some(GameState::Menu);
+/// Confirm resumes the run, or retries once the name of a high score was entered,
+/// back goes to the main menu, except online where the other peer keeps playing.
+fn resume_or_quit(
+    action_state: Res<ActionState>,
+    session: Option<Res<OnlineSession>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut last_activity: ResMut<LastActivity>,
+) {
+    if action_state.just_pressed(Action::Confirm) {
+        game_state.set(GameState::Playing);
+        last_activity.0.reset();
+    } else if action_state.just_pressed(Action::Back) && session.is_none() {
+        game_state.set(GameState::MainMenu);
     }
 }
@@ -1,35 +1,51 @@
+use bevy::ecs::query::Has;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 
+use crate::actions::{Action, LocalPlayers};
+use crate::arena::Arena;
+use crate::bullets::Bullet;
 use crate::menu::LastActivity;
-use crate::player::Player;
+use crate::player::{Dash, Player, PlayerInput};
+use crate::simulation_time::SimulationTime;
+use crate::spatial_hash::Collider;
 use crate::utils::move_towards;
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct MoveTarget {
     pub target: Option<Vec2>,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct MoveDirection(pub Vec2);
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct MoveSpeed(pub f32);
 
+/// Blocked by the [`Arena`] walls and obstacles, like [`move_direction`].
 pub fn move_targets(
-    time: Res<Time>,
-    mut q_moving: Query<(&mut Transform, &mut MoveTarget, &MoveSpeed)>,
+    time: Res<SimulationTime>,
+    arena: Res<Arena>,
+    mut q_moving: Query<(
+        &mut Transform,
+        &mut MoveTarget,
+        &MoveSpeed,
+        Option<&Collider>,
+    )>,
 ) {
-    for (mut transform, mut target, speed) in q_moving.iter_mut() {
+    for (mut transform, mut target, speed, collider) in q_moving.iter_mut() {
         if match target.target {
             Some(target) => {
-                let position = move_towards(
+                let mut position = move_towards(
                     transform.translation.xy(),
                     target,
                     speed.0 * time.delta_seconds(),
                 );
+                if let Some(collider) = collider {
+                    position = arena.constrain(position, collider.radius);
+                }
                 transform.translation = position.extend(2f32);
-                transform.translation.xy().distance_squared(target) <= 0.1f32
+                position.distance_squared(target) <= 0.1f32
             }
             None => false,
         } {
@@ -38,40 +54,65 @@ pub fn move_targets(
     }
 }
 
+/// Blocked by the [`Arena`] walls and obstacles, except bullets, see [`crate::bullets::bullets_walls`].
 pub fn move_direction(
-    time: Res<Time>,
-    mut q_moving: Query<(&mut Transform, &MoveDirection, &MoveSpeed)>,
+    time: Res<SimulationTime>,
+    arena: Res<Arena>,
+    mut q_moving: Query<(
+        &mut Transform,
+        &MoveDirection,
+        &MoveSpeed,
+        Option<&Collider>,
+        Has<Bullet>,
+    )>,
 ) {
-    for (mut transform, move_direction, speed) in q_moving.iter_mut() {
-        transform.translation += (move_direction.0 * speed.0 * time.delta_seconds()).extend(0f32);
+    for (mut transform, move_direction, speed, collider, is_bullet) in q_moving.iter_mut() {
+        let mut position =
+            transform.translation.xy() + move_direction.0 * speed.0 * time.delta_seconds();
+        if let (Some(collider), false) = (collider, is_bullet) {
+            position = arena.constrain(position, collider.radius);
+        }
+        transform.translation = position.extend(transform.translation.z);
     }
 }
 
-pub fn wasd_movement(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut q_moving: Query<(&mut MoveDirection, &MoveSpeed), With<Player>>,
+pub fn move_actions(
+    local_players: Res<LocalPlayers>,
+    mut q_inputs: Query<(&Player, &mut PlayerInput)>,
     mut last_activity: ResMut<LastActivity>,
 ) {
-    for (mut move_direction, speed) in q_moving.iter_mut() {
-        let mut direction = Vec2::ZERO;
-        if keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up) {
-            direction += Vec2::Y;
-        }
-        if keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down) {
-            direction -= Vec2::Y;
-        }
-        if keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left) {
-            direction -= Vec2::X;
-        }
-        if keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right) {
-            direction += Vec2::X;
+    for (player, mut input) in q_inputs.iter_mut() {
+        let Some((_, action_state)) = local_players.get(player.0) else {
+            continue;
+        };
+        input.direction = action_state.direction(
+            Action::MoveUp,
+            Action::MoveDown,
+            Action::MoveLeft,
+            Action::MoveRight,
+        );
+        input.dash = action_state.pressed(Action::Dash);
+        if input.direction != Vec2::ZERO || input.dash {
+            last_activity.0.reset();
         }
-        if direction.length_squared() > 0.0 {
-            direction = direction.normalize();
+    }
+}
+
+pub fn player_input_movement(
+    time: Res<SimulationTime>,
+    mut q_moving: Query<(&mut MoveDirection, &PlayerInput, Option<&mut Dash>), With<Player>>,
+) {
+    let elapsed_seconds = time.elapsed_seconds();
+    for (mut move_direction, input, dash) in q_moving.iter_mut() {
+        move_direction.0 = input.direction;
+        let Some(mut dash) = dash else {
+            continue;
+        };
+        if input.dash && input.direction != Vec2::ZERO && dash.is_ready(elapsed_seconds) {
+            dash.started_at = Some(elapsed_seconds);
         }
-        if direction != Vec2::ZERO {
-            last_activity.0.reset();
+        if dash.is_dashing(elapsed_seconds) {
+            move_direction.0 *= dash.speed_multiplier;
         }
-        move_direction.0 = direction;
     }
 }
@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::arena::{Arena, Obstacle};
+
+/// Size of the cells used for the arenas, smaller than the smallest collider.
+pub const CELL_SIZE: f32 = 16f32;
+
+/// Square cells covering an [`Arena`], free when a circle of `clearance` radius fits at their center,
+/// inside the walls and out of every obstacle.
+///
+/// Cells are connected to their 4 neighbours, two free neighbours are close enough for the circle
+/// to go from one to the other, as long as the cells are smaller than the clearance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavGrid {
+    /// Center of the cell at the bottom left corner.
+    origin: Vec2,
+    cell_size: f32,
+    clearance: f32,
+    size: UVec2,
+    free: Vec<bool>,
+}
+
+impl NavGrid {
+    pub fn new(arena: &Arena, cell_size: f32, clearance: f32) -> Self {
+        let size = (arena.half_size * 2f32 / cell_size)
+            .floor()
+            .as_uvec2()
+            .max(UVec2::ONE);
+        let origin = -(size.as_vec2() - 1f32) * cell_size / 2f32;
+        let inner = arena.half_size - clearance;
+        let mut grid = Self {
+            origin,
+            cell_size,
+            clearance,
+            size,
+            free: Vec::new(),
+        };
+        grid.free = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| UVec2::new(x, y)))
+            .map(|cell| grid.center(cell).abs().cmple(inner).all())
+            .collect();
+        for obstacle in arena.obstacles.iter() {
+            grid.block(obstacle);
+        }
+        grid
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn clearance(&self) -> f32 {
+        self.clearance
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Number of cells.
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+
+    /// Position of `cell` in the vectors indexed by cell, like [`NavGrid::reachable`].
+    pub fn index(&self, cell: UVec2) -> usize {
+        (cell.y * self.size.x + cell.x) as usize
+    }
+
+    /// The cell `step` away from `cell`, `None` outside of the grid.
+    pub fn offset(&self, cell: UVec2, step: IVec2) -> Option<UVec2> {
+        let next = cell.as_ivec2() + step;
+        (next.cmpge(IVec2::ZERO).all() && next.cmplt(self.size.as_ivec2()).all())
+            .then(|| next.as_uvec2())
+    }
+
+    /// The cell `position` is in, `None` outside of the grid.
+    pub fn cell(&self, position: Vec2) -> Option<UVec2> {
+        let cell = ((position - self.origin) / self.cell_size + 0.5f32).floor();
+        (cell.cmpge(Vec2::ZERO).all() && cell.cmplt(self.size.as_vec2()).all())
+            .then(|| cell.as_uvec2())
+    }
+
+    pub fn center(&self, cell: UVec2) -> Vec2 {
+        self.origin + cell.as_vec2() * self.cell_size
+    }
+
+    pub fn is_free(&self, cell: UVec2) -> bool {
+        self.free[self.index(cell)]
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = UVec2> + '_ {
+        (0..self.size.y).flat_map(move |y| (0..self.size.x).map(move |x| UVec2::new(x, y)))
+    }
+
+    /// The free cells next to `cell`.
+    pub fn neighbours(&self, cell: UVec2) -> impl Iterator<Item = UVec2> + '_ {
+        [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+            .into_iter()
+            .filter_map(move |step| self.offset(cell, step))
+            .filter(|next| self.is_free(*next))
+    }
+
+    /// Marks the cells the obstacle overlaps as blocked, and returns them, only looking at the ones
+    /// around it.
+    pub fn block(&mut self, obstacle: &Obstacle) -> Vec<UVec2> {
+        let (min, max) = obstacle.shape.bounds();
+        let reach = Vec2::splat(self.clearance + self.cell_size);
+        let to_cell = |position: Vec2| {
+            ((position - self.origin) / self.cell_size)
+                .floor()
+                .clamp(Vec2::ZERO, self.size.as_vec2() - 1f32)
+                .as_uvec2()
+        };
+        let (from, to) = (to_cell(min - reach), to_cell(max + reach));
+        let mut blocked = Vec::new();
+        for y in from.y..=to.y {
+            for x in from.x..=to.x {
+                let cell = UVec2::new(x, y);
+                let index = self.index(cell);
+                if self.free[index]
+                    && obstacle
+                        .shape
+                        .push_out(self.center(cell), self.clearance)
+                        .is_some()
+                {
+                    self.free[index] = false;
+                    blocked.push(cell);
+                }
+            }
+        }
+        blocked
+    }
+
+    /// The circle can go straight from `from` to `to`, every cell along the way being free.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let steps = (from.distance(to) / (self.cell_size / 2f32))
+            .ceil()
+            .max(1f32) as u32;
+        (0..=steps).all(|step| {
+            self.cell(from.lerp(to, step as f32 / steps as f32))
+                .map_or(false, |cell| self.is_free(cell))
+        })
+    }
+
+    /// Whether each cell can be reached from `from` through free cells, by index.
+    pub fn reachable(&self, from: UVec2) -> Vec<bool> {
+        let mut reached = vec![false; self.free.len()];
+        if !self.is_free(from) {
+            return reached;
+        }
+        reached[self.index(from)] = true;
+        let mut queue = VecDeque::from([from]);
+        while let Some(cell) = queue.pop_front() {
+            for next in self.neighbours(cell) {
+                let index = self.index(next);
+                if !reached[index] {
+                    reached[index] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Every free cell can be reached from `from`, there is no enclosed pocket.
+    pub fn is_connected(&self, from: UVec2) -> bool {
+        let reached = self.reachable(from);
+        self.free
+            .iter()
+            .zip(reached)
+            .all(|(free, reached)| !free || reached)
+    }
+}
@@ -0,0 +1,297 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy::{math::Vec3Swizzles, prelude::*};
+
+use crate::{
+    arena::{Arena, Obstacle},
+    nav_grid::{NavGrid, CELL_SIZE},
+    player::Player,
+};
+
+/// Radius of most enemies, bigger ones squeeze along the obstacles.
+pub const CLEARANCE: f32 = 18f32;
+/// Cost of going to the next cell, going diagonally costs [`DIAGONAL`].
+const STRAIGHT: u32 = 10;
+const DIAGONAL: u32 = 14;
+/// How many cells of the path [`FlowField::detour`] looks ahead for one it can go straight to.
+const LOOK_AHEAD: usize = 4;
+
+const STEPS: [(IVec2, u32); 8] = [
+    (IVec2::X, STRAIGHT),
+    (IVec2::NEG_X, STRAIGHT),
+    (IVec2::Y, STRAIGHT),
+    (IVec2::NEG_Y, STRAIGHT),
+    (IVec2::new(1, 1), DIAGONAL),
+    (IVec2::new(1, -1), DIAGONAL),
+    (IVec2::new(-1, 1), DIAGONAL),
+    (IVec2::new(-1, -1), DIAGONAL),
+];
+/// No cell, in [`FlowField::parents`].
+const NO_PARENT: u32 = u32::MAX;
+
+/// Length of the shortest path from each cell of the arena to the nearest player, the AIs go down
+/// it to get around the obstacles, see [`FlowField::detour`].
+///
+/// Only updated when a player enters another cell or obstacles are added, see [`FlowField::repair`],
+/// and computed again in full when the arena changes otherwise.
+/// Both come from the current state of the simulation, so it doesn't need to be rolled back.
+#[derive(Resource, Default)]
+pub struct FlowField {
+    grid: Option<NavGrid>,
+    /// The walls and obstacles the grid was built from.
+    built_from: (Vec2, Vec<Obstacle>),
+    goals: Vec<UVec2>,
+    distances: Vec<u32>,
+    /// The cell each shortest path goes through next, by index, [`NO_PARENT`] for the goals and
+    /// the cells with no path.
+    parents: Vec<u32>,
+}
+
+/// How [`FlowField::update_grid`] changed the grid.
+enum GridChange {
+    Unchanged,
+    /// Obstacles were added, these cells aren't free anymore.
+    Blocked(Vec<UVec2>),
+    Rebuilt,
+}
+
+impl FlowField {
+    /// Blocks the obstacles added to the arena, or builds the grid again when it changed otherwise.
+    fn update_grid(&mut self, arena: &Arena) -> GridChange {
+        if let Some(grid) = &mut self.grid {
+            if self.built_from.0 == arena.half_size
+                && arena.obstacles.starts_with(&self.built_from.1)
+            {
+                let added = &arena.obstacles[self.built_from.1.len()..];
+                if added.is_empty() {
+                    return GridChange::Unchanged;
+                }
+                let blocked = added
+                    .iter()
+                    .flat_map(|obstacle| grid.block(obstacle))
+                    .collect();
+                self.built_from.1 = arena.obstacles.clone();
+                return GridChange::Blocked(blocked);
+            }
+        }
+        self.grid = Some(NavGrid::new(arena, CELL_SIZE, CLEARANCE));
+        self.built_from = (arena.half_size, arena.obstacles.clone());
+        GridChange::Rebuilt
+    }
+
+    /// Moves the goals to the cells of the `players`, and updates the distances to match.
+    pub fn update(&mut self, arena: &Arena, players: impl IntoIterator<Item = Vec2>) {
+        let change = self.update_grid(arena);
+        let Some(grid) = &self.grid else {
+            return;
+        };
+        // Sorted, as the order of the players may change with a rollback.
+        let mut goals = players
+            .into_iter()
+            .filter_map(|position| Self::free_cell(grid, position))
+            .collect::<Vec<_>>();
+        goals.sort_by_key(|cell| (cell.y, cell.x));
+        goals.dedup();
+        match change {
+            GridChange::Rebuilt => {
+                self.goals = goals;
+                self.compute();
+            }
+            GridChange::Blocked(blocked) => self.repair(goals, &blocked),
+            GridChange::Unchanged if goals != self.goals => self.repair(goals, &[]),
+            GridChange::Unchanged => {}
+        }
+    }
+
+    /// Length of the shortest path from each cell to the nearest goal, by index in the grid,
+    /// `u32::MAX` when there is none.
+    pub fn distances(&self) -> &[u32] {
+        &self.distances
+    }
+
+    /// The cells next to `cell` that can be reached from it, with the cost of going there.
+    /// Diagonals don't cut the corners of the blocked cells.
+    fn steps(grid: &NavGrid, cell: UVec2) -> impl Iterator<Item = (UVec2, u32)> + '_ {
+        let free = move |step: IVec2| grid.offset(cell, step).filter(|next| grid.is_free(*next));
+        STEPS.into_iter().filter_map(move |(step, cost)| {
+            let next = free(step)?;
+            if step.x != 0 && step.y != 0 {
+                free(IVec2::new(step.x, 0))?;
+                free(IVec2::new(0, step.y))?;
+            }
+            Some((next, cost))
+        })
+    }
+
+    /// Dijkstra from every goal at once, over the whole grid.
+    fn compute(&mut self) {
+        let Some(grid) = &self.grid else {
+            return;
+        };
+        self.distances.clear();
+        self.distances.resize(grid.len(), u32::MAX);
+        self.parents.clear();
+        self.parents.resize(grid.len(), NO_PARENT);
+        let mut queue = BinaryHeap::new();
+        for goal in self.goals.iter() {
+            self.distances[grid.index(*goal)] = 0;
+            queue.push(Reverse((0, goal.y, goal.x)));
+        }
+        Self::relax(grid, &mut self.distances, &mut self.parents, queue);
+    }
+
+    /// Moves the goals to `goals` and goes around the `blocked` cells, only going over the cells
+    /// whose shortest path changed.
+    ///
+    /// The paths ending at a removed goal or going through a blocked cell are dropped, and found
+    /// again from the cells around them, which kept theirs, and from the goals.
+    fn repair(&mut self, goals: Vec<UVec2>, blocked: &[UVec2]) {
+        let Some(grid) = &self.grid else {
+            return;
+        };
+        // A diagonal step between two cells can't cut the corner of a blocked cell either,
+        // so the paths through the cells around it are dropped too.
+        let around_blocked = blocked.iter().flat_map(|cell| {
+            STEPS
+                .iter()
+                .filter_map(|(step, _)| grid.offset(*cell, *step))
+                .chain([*cell])
+        });
+        let mut stack = self
+            .goals
+            .iter()
+            .filter(|goal| !goals.contains(goal))
+            .copied()
+            .chain(around_blocked)
+            .collect::<Vec<_>>();
+        let mut dropped = Vec::new();
+        while let Some(cell) = stack.pop() {
+            let index = grid.index(cell);
+            if self.distances[index] == u32::MAX {
+                continue;
+            }
+            self.distances[index] = u32::MAX;
+            self.parents[index] = NO_PARENT;
+            dropped.push(cell);
+            stack.extend(
+                STEPS
+                    .iter()
+                    .filter_map(|(step, _)| grid.offset(cell, *step))
+                    .filter(|next| self.parents[grid.index(*next)] == index as u32),
+            );
+        }
+
+        let mut queue = BinaryHeap::new();
+        for cell in dropped {
+            for (next, _) in Self::steps(grid, cell) {
+                let distance = self.distances[grid.index(next)];
+                if distance != u32::MAX {
+                    queue.push(Reverse((distance, next.y, next.x)));
+                }
+            }
+        }
+        // The goals kept can have been dropped with the cells around a blocked one.
+        for goal in goals.iter() {
+            let index = grid.index(*goal);
+            self.distances[index] = 0;
+            self.parents[index] = NO_PARENT;
+            queue.push(Reverse((0, goal.y, goal.x)));
+        }
+        self.goals = goals;
+        Self::relax(grid, &mut self.distances, &mut self.parents, queue);
+    }
+
+    /// Dijkstra from the cells in `queue`, shortening the paths of the cells around them.
+    fn relax(
+        grid: &NavGrid,
+        distances: &mut [u32],
+        parents: &mut [u32],
+        mut queue: BinaryHeap<Reverse<(u32, u32, u32)>>,
+    ) {
+        while let Some(Reverse((distance, y, x))) = queue.pop() {
+            let cell = UVec2::new(x, y);
+            let index = grid.index(cell);
+            if distance > distances[index] {
+                continue;
+            }
+            for (next, cost) in Self::steps(grid, cell) {
+                let next_index = grid.index(next);
+                if distance + cost < distances[next_index] {
+                    distances[next_index] = distance + cost;
+                    parents[next_index] = index as u32;
+                    queue.push(Reverse((distance + cost, next.y, next.x)));
+                }
+            }
+        }
+    }
+
+    fn distance(&self, grid: &NavGrid, cell: UVec2) -> u32 {
+        self.distances
+            .get(grid.index(cell))
+            .copied()
+            .unwrap_or(u32::MAX)
+    }
+
+    /// The free cell of `position`, or the closest free one around it when it is against an obstacle.
+    fn free_cell(grid: &NavGrid, position: Vec2) -> Option<UVec2> {
+        let cell = grid.cell(position)?;
+        if grid.is_free(cell) {
+            return Some(cell);
+        }
+        STEPS
+            .into_iter()
+            .filter_map(|(step, _)| grid.offset(cell, step))
+            .filter(|next| grid.is_free(*next))
+            .min_by(|a, b| {
+                let distance = |cell: &UVec2| grid.center(*cell).distance_squared(position);
+                distance(a).total_cmp(&distance(b))
+            })
+    }
+
+    /// Which way to go from `position` to reach `player` around the obstacles, toward the
+    /// furthest cell of the path it can go straight to.
+    /// `None` when nothing is in the way, or there is no path.
+    ///
+    /// Both ends are moved to their free cell, as colliders pushed against an obstacle are in
+    /// blocked ones.
+    pub fn detour(&self, position: Vec2, player: Vec2) -> Option<Vec2> {
+        let grid = self.grid.as_ref()?;
+        let mut cell = Self::free_cell(grid, position)?;
+        let from = grid.center(cell);
+        let to = grid.center(Self::free_cell(grid, player)?);
+        if grid.line_of_sight(from, to) {
+            return None;
+        }
+        let mut distance = self.distance(grid, cell);
+        if distance == u32::MAX {
+            return None;
+        }
+        let mut waypoint = from;
+        for _ in 0..LOOK_AHEAD {
+            let Some((next, next_distance)) = Self::steps(grid, cell)
+                .map(|(next, _)| (next, self.distance(grid, next)))
+                .min_by_key(|(_, distance)| *distance)
+                .filter(|(_, next_distance)| *next_distance < distance)
+            else {
+                break;
+            };
+            (cell, distance) = (next, next_distance);
+            if grid.line_of_sight(from, grid.center(cell)) {
+                waypoint = grid.center(cell);
+            }
+        }
+        Some((waypoint - position).normalize_or_zero())
+    }
+}
+
+/// Updates the [`FlowField`] when a player changed cell or the arena changed.
+pub fn update_flow_field(
+    arena: Res<Arena>,
+    q_players: Query<&Transform, With<Player>>,
+    mut flow_field: ResMut<FlowField>,
+) {
+    flow_field.update(
+        &arena,
+        q_players.iter().map(|transform| transform.translation.xy()),
+    );
+}
@@ -0,0 +1,488 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use bevy::{prelude::*, utils::Duration};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    arena::SelectedArena,
+    menu::GameState,
+    player::{Player, PlayerInput},
+    replay::{record_or_play_inputs, write_input, Reader, ReplayError},
+    rng::GameRng,
+    rollback::Snapshot,
+    simulation_time::SimulationTime,
+    GameMode, GameSet, Lives, SimulationSet,
+};
+
+/// Online co-op between two peers, with rollback.
+///
+/// Each peer simulates its ticks right away, predicting that the other player keeps doing
+/// what they did last. When the actual inputs arrive and differ, the simulation goes back
+/// to the tick they were mispredicted from with a [`Snapshot`], and simulates again up to the present.
+///
+/// Inputs go over UDP, each packet repeats the inputs the other peer hasn't acknowledged yet,
+/// so a lost packet is made up by the next one.
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.configure_set(FixedUpdate, SimulationSet.run_if(session_can_advance));
+        let in_session = resource_exists::<OnlineSession>();
+        app.add_systems(Startup, start_session.run_if(in_session.clone()));
+        app.add_systems(PreUpdate, receive_and_rollback.run_if(in_session.clone()));
+        app.add_systems(
+            FixedUpdate,
+            (
+                save_snapshot.in_set(GameSet::Snapshot),
+                // A recording keeps the inputs actually played.
+                exchange_inputs
+                    .before(record_or_play_inputs)
+                    .in_set(GameSet::Replay),
+                hold_state_transition.in_set(GameSet::Despawn),
+            )
+                .run_if(in_session.clone()),
+        );
+        app.add_systems(Update, start_online_run.run_if(in_session.clone()));
+        app.add_systems(PostUpdate, send_inputs.run_if(in_session));
+    }
+}
+
+/// Local inputs are applied this many ticks after being read, which gives them time
+/// to reach the other peer and avoids most rollbacks.
+pub const INPUT_DELAY: u64 = 2;
+/// Ticks simulated ahead of the last confirmed one at most, the simulation waits beyond.
+pub const MAX_PREDICTION: u64 = 8;
+/// The other peer is considered gone after this long without any packet.
+pub const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Inputs sent in a single packet at most, the next packets send the rest.
+const MAX_INPUTS_PER_PACKET: usize = 64;
+
+const MAGIC: &[u8; 4] = b"CMNP";
+const PACKET_HELLO: u8 = 0;
+const PACKET_INPUTS: u8 = 1;
+
+/// Degrades the outgoing packets, to try the netcode out on a local network.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetworkConditions {
+    /// Added to every packet.
+    pub latency: Duration,
+    /// Up to this much more at random, so packets can arrive out of order.
+    pub jitter: Duration,
+    /// Probability to drop each packet, between 0 and 1.
+    pub packet_loss: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// Waiting for the other peer, the run doesn't start until it answers.
+    Synchronizing,
+    Running,
+    /// The other peer stopped answering, its player stands still from then on.
+    Disconnected,
+}
+
+/// Insert it before [`crate::SimulationPlugin`] to play online, as player 0 or 1.
+///
+/// Both peers play with the seed of player 0, in [`GameMode::Coop`] with shared lives,
+/// in the first [`crate::arena::Arena`].
+#[derive(Resource)]
+pub struct OnlineSession {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    local_player: usize,
+    conditions: NetworkConditions,
+    conditions_rng: ChaCha8Rng,
+    /// Packets held back by the conditions, with when to send them.
+    delayed: Vec<(Duration, Vec<u8>)>,
+    state: SessionState,
+    local_seed: u64,
+    remote_seed: Option<u64>,
+    /// The other peer sent a hello while running, it didn't get ours yet.
+    hello_requested: bool,
+    last_received: Duration,
+    /// By tick, including the ones ahead because of [`INPUT_DELAY`].
+    local_inputs: BTreeMap<u64, PlayerInput>,
+    remote_inputs: BTreeMap<u64, PlayerInput>,
+    /// Every remote input up to this tick was received.
+    remote_confirmed: u64,
+    /// The other peer received every local input up to this tick.
+    remote_ack: u64,
+    /// Remote inputs the simulated ticks guessed, until the actual ones arrive.
+    predictions: BTreeMap<u64, PlayerInput>,
+    /// Earliest tick simulated with a wrong prediction.
+    rollback_from: Option<u64>,
+    /// By tick, the state once that tick was simulated.
+    snapshots: BTreeMap<u64, Snapshot>,
+    /// State the simulation asked for, held until the tick asking for it is confirmed
+    /// as a rollback could change its mind.
+    pending_transition: Option<(u64, GameState)>,
+    rollbacks: u32,
+}
+
+impl OnlineSession {
+    pub fn bind(local_port: u16, peer: SocketAddr, local_player: usize) -> io::Result<Self> {
+        Self::new(
+            UdpSocket::bind(("0.0.0.0", local_port))?,
+            peer,
+            local_player,
+        )
+    }
+
+    pub fn new(socket: UdpSocket, peer: SocketAddr, local_player: usize) -> io::Result<Self> {
+        assert!(local_player < 2, "Online sessions are for two players");
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            peer,
+            local_player,
+            conditions: NetworkConditions::default(),
+            conditions_rng: ChaCha8Rng::seed_from_u64(local_player as u64),
+            delayed: Vec::new(),
+            state: SessionState::Synchronizing,
+            local_seed: 0,
+            remote_seed: None,
+            hello_requested: false,
+            last_received: Duration::ZERO,
+            // Nothing was read for the first ticks, because of the delay.
+            local_inputs: (1..=INPUT_DELAY)
+                .map(|tick| (tick, PlayerInput::default()))
+                .collect(),
+            remote_inputs: BTreeMap::new(),
+            remote_confirmed: 0,
+            remote_ack: 0,
+            predictions: BTreeMap::new(),
+            rollback_from: None,
+            snapshots: BTreeMap::new(),
+            pending_transition: None,
+            rollbacks: 0,
+        })
+    }
+
+    pub fn with_conditions(mut self, conditions: NetworkConditions) -> Self {
+        self.conditions = conditions;
+        self
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    fn remote_player(&self) -> usize {
+        1 - self.local_player
+    }
+
+    /// Every input up to this tick is known, it won't be rolled back.
+    pub fn confirmed_tick(&self) -> u64 {
+        match self.state {
+            SessionState::Disconnected => u64::MAX,
+            _ => self.remote_confirmed,
+        }
+    }
+
+    /// How many times the simulation went back to correct a prediction.
+    pub fn rollbacks(&self) -> u32 {
+        self.rollbacks
+    }
+
+    /// The input of the other player for this tick, predicted when it didn't arrive yet.
+    fn remote_input(&mut self, tick: u64) -> PlayerInput {
+        if let Some(input) = self.remote_inputs.get(&tick) {
+            return *input;
+        }
+        if self.state == SessionState::Disconnected {
+            return PlayerInput::default();
+        }
+        let predicted = self
+            .remote_inputs
+            .range(..tick)
+            .next_back()
+            .map(|(_, input)| *input)
+            .unwrap_or_default();
+        self.predictions.insert(tick, predicted);
+        predicted
+    }
+
+    fn receive(&mut self, now: Duration) {
+        let mut buffer = [0u8; 2048];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) if from == self.peer => {
+                    if let Err(error) = self.handle_packet(&buffer[..len]) {
+                        warn!("Ignoring a malformed packet: {error:?}");
+                        continue;
+                    }
+                    self.last_received = now;
+                }
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                // Like a refused connection, when the other peer isn't listening yet.
+                Err(_) => {}
+            }
+        }
+        if self.state == SessionState::Running && now > self.last_received + DISCONNECT_TIMEOUT {
+            warn!("The other peer stopped answering");
+            self.state = SessionState::Disconnected;
+            // Its player was predicted to keep going, it stands still instead.
+            if let Some((&tick, _)) = self.predictions.iter().next() {
+                self.rollback_from = Some(self.rollback_from.map_or(tick, |from| from.min(tick)));
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: &[u8]) -> Result<(), ReplayError> {
+        let mut reader = Reader(packet);
+        if reader.take::<4>()? != *MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        match reader.u8()? {
+            PACKET_HELLO => {
+                let player = reader.u8()? as usize;
+                let seed = u64::from_le_bytes(reader.take()?);
+                if player != self.remote_player() {
+                    warn!("The other peer plays as player {player} too");
+                    return Ok(());
+                }
+                self.remote_seed = Some(seed);
+                match self.state {
+                    SessionState::Synchronizing => {
+                        info!("Connected to {}", self.peer);
+                        self.state = SessionState::Running;
+                    }
+                    _ => self.hello_requested = true,
+                }
+            }
+            PACKET_INPUTS => {
+                let ack = u64::from_le_bytes(reader.take()?);
+                let first = u64::from_le_bytes(reader.take()?);
+                let count = reader.u8()? as u64;
+                self.remote_ack = self.remote_ack.max(ack);
+                for tick in first..first + count {
+                    let input = reader.input()?;
+                    if tick <= self.remote_confirmed || self.remote_inputs.contains_key(&tick) {
+                        continue;
+                    }
+                    self.remote_inputs.insert(tick, input);
+                    if self
+                        .predictions
+                        .remove(&tick)
+                        .is_some_and(|predicted| predicted != input)
+                    {
+                        self.rollback_from =
+                            Some(self.rollback_from.map_or(tick, |from| from.min(tick)));
+                    }
+                }
+                while self
+                    .remote_inputs
+                    .contains_key(&(self.remote_confirmed + 1))
+                {
+                    self.remote_confirmed += 1;
+                }
+            }
+            kind => warn!("Unknown packet kind {kind}"),
+        }
+        Ok(())
+    }
+
+    /// Drops the inputs neither the next ticks nor a rollback can need anymore, once `tick` was simulated.
+    fn forget_inputs(&mut self, tick: u64) {
+        let confirmed = self.remote_confirmed.min(tick);
+        // The last confirmed remote input predicts the next ones.
+        self.remote_inputs = self.remote_inputs.split_off(&confirmed.max(1));
+        self.predictions = self.predictions.split_off(&(confirmed + 1));
+        let needed = confirmed.min(self.remote_ack) + 1;
+        self.local_inputs = self.local_inputs.split_off(&needed);
+    }
+
+    fn send(&mut self, now: Duration) {
+        if self.state == SessionState::Synchronizing || self.hello_requested {
+            self.hello_requested = false;
+            let mut packet = MAGIC.to_vec();
+            packet.push(PACKET_HELLO);
+            packet.push(self.local_player as u8);
+            packet.extend_from_slice(&self.local_seed.to_le_bytes());
+            self.transmit(packet, now);
+        }
+        if self.state != SessionState::Synchronizing {
+            let inputs = self
+                .local_inputs
+                .range(self.remote_ack + 1..)
+                .take(MAX_INPUTS_PER_PACKET)
+                .map(|(_, input)| *input)
+                .collect::<Vec<_>>();
+            let mut packet = MAGIC.to_vec();
+            packet.push(PACKET_INPUTS);
+            packet.extend_from_slice(&self.remote_confirmed.to_le_bytes());
+            packet.extend_from_slice(&(self.remote_ack + 1).to_le_bytes());
+            packet.push(inputs.len() as u8);
+            for input in inputs.iter() {
+                write_input(&mut packet, input);
+            }
+            self.transmit(packet, now);
+        }
+        let (due, delayed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(send_at, _)| *send_at <= now);
+        self.delayed = delayed;
+        for (_, packet) in due {
+            if let Err(error) = self.socket.send_to(&packet, self.peer) {
+                debug!("Couldn't send to {}: {error}", self.peer);
+            }
+        }
+    }
+
+    fn transmit(&mut self, packet: Vec<u8>, now: Duration) {
+        if self.conditions_rng.gen::<f32>() < self.conditions.packet_loss {
+            return;
+        }
+        let jitter = self.conditions.jitter.mul_f32(self.conditions_rng.gen());
+        self.delayed
+            .push((now + self.conditions.latency + jitter, packet));
+    }
+}
+
+fn start_session(
+    mut session: ResMut<OnlineSession>,
+    rng: Res<GameRng>,
+    mut mode: ResMut<GameMode>,
+    mut selected_arena: ResMut<SelectedArena>,
+) {
+    session.local_seed = rng.seed();
+    *selected_arena = SelectedArena::default();
+    *mode = GameMode::Coop {
+        players: 2,
+        lives: Lives::Shared,
+    };
+}
+
+fn session_can_advance(session: Option<Res<OnlineSession>>, time: Res<SimulationTime>) -> bool {
+    let Some(session) = session else {
+        return true;
+    };
+    session.state != SessionState::Synchronizing
+        && session.pending_transition.is_none()
+        && time.tick < session.confirmed_tick().saturating_add(MAX_PREDICTION)
+}
+
+/// Reads the packets, then rolls back and simulates again the mispredicted ticks, if any.
+fn receive_and_rollback(world: &mut World) {
+    let now = world.resource::<Time>().elapsed();
+    let current_tick = world.resource::<SimulationTime>().tick;
+    let rollback_from = world.resource_scope(|world, mut session: Mut<OnlineSession>| {
+        let synchronizing = session.state == SessionState::Synchronizing;
+        session.receive(now);
+        if synchronizing && session.state == SessionState::Running {
+            let seed = match session.local_player {
+                0 => session.local_seed,
+                _ => session.remote_seed.unwrap_or(session.local_seed),
+            };
+            world.insert_resource(GameRng::from_seed(seed));
+        }
+        let rollback_from = session
+            .rollback_from
+            .take()
+            .filter(|tick| *tick <= current_tick)?;
+        let Some(snapshot) = session.snapshots.get(&(rollback_from - 1)) else {
+            warn!("No snapshot to roll back to tick {rollback_from}");
+            return None;
+        };
+        snapshot.restore(world);
+        session.snapshots.split_off(&rollback_from);
+        session.predictions.split_off(&rollback_from);
+        // The ticks simulated again ask for it again if they still do.
+        session.pending_transition = None;
+        session.rollbacks += 1;
+        Some(rollback_from)
+    });
+    if let Some(rollback_from) = rollback_from {
+        for _ in rollback_from..=current_tick {
+            world.run_schedule(FixedUpdate);
+        }
+    }
+    let mut session = world.resource_mut::<OnlineSession>();
+    let Some((tick, state)) = session.pending_transition.clone() else {
+        return;
+    };
+    if tick <= session.confirmed_tick() {
+        session.pending_transition = None;
+        world.resource_mut::<NextState<GameState>>().set(state);
+    }
+}
+
+fn save_snapshot(world: &mut World) {
+    let tick = world.resource::<SimulationTime>().tick;
+    let snapshot = Snapshot::save(world);
+    let mut session = world.resource_mut::<OnlineSession>();
+    let oldest_needed = session.confirmed_tick().min(tick);
+    session.snapshots = session.snapshots.split_off(&oldest_needed);
+    session.snapshots.insert(tick, snapshot);
+}
+
+/// Stores the local input for a later tick, then feeds the inputs of this tick to the players.
+fn exchange_inputs(
+    time: Res<SimulationTime>,
+    mut session: ResMut<OnlineSession>,
+    mut q_inputs: Query<(&Player, &mut PlayerInput)>,
+) {
+    let local_player = session.local_player;
+    // When simulating a tick again, the input read the first time is kept.
+    session
+        .local_inputs
+        .entry(time.tick + INPUT_DELAY)
+        .or_insert_with(|| {
+            q_inputs
+                .iter()
+                .find(|(player, _)| player.0 == local_player)
+                .map(|(_, input)| *input)
+                .unwrap_or_default()
+        });
+    let local = session
+        .local_inputs
+        .get(&time.tick)
+        .copied()
+        .unwrap_or_default();
+    let remote = session.remote_input(time.tick);
+    session.forget_inputs(time.tick);
+    for (player, mut input) in q_inputs.iter_mut() {
+        *input = if player.0 == local_player {
+            local
+        } else {
+            remote
+        };
+    }
+}
+
+fn hold_state_transition(
+    time: Res<SimulationTime>,
+    mut session: ResMut<OnlineSession>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if let Some(state) = next_state.0.take() {
+        session.pending_transition = Some((time.tick, state));
+    }
+}
+
+/// Both peers start a run as soon as they are connected, and a new one after each game over.
+fn start_online_run(
+    session: Res<OnlineSession>,
+    game_state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if session.state != SessionState::Synchronizing
+        && matches!(game_state.get(), GameState::MainMenu | GameState::GameOver)
+    {
+        next_state.set(GameState::Playing);
+    }
+}
+
+fn send_inputs(time: Res<Time>, mut session: ResMut<OnlineSession>) {
+    session.send(time.elapsed());
+}
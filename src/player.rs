@@ -1,73 +1,181 @@
 use bevy::{math::Vec3Swizzles, prelude::*, window::PrimaryWindow};
 
 use crate::{
-    bullets::CommandsSpawnBullet, menu::LastActivity, movement::MoveTarget, Cooldown, TeamIdx,
+    actions::{Action, LocalPlayers},
+    bullets::{BulletBehaviour, CommandsSpawnBullet, Weapon},
+    menu::LastActivity,
+    movement::{MoveDirection, MoveSpeed, MoveTarget},
+    simulation_time::SimulationTime,
+    spatial_hash::Collider,
+    Cooldown, Health, TeamIdx,
 };
 
-#[derive(Component, Debug)]
-pub struct Player;
+/// Index of a local player, below [`crate::GameMode::MAX_PLAYERS`].
+///
+/// It picks the devices of the player, see [`LocalPlayers`], and their team, see [`TeamIdx::player`].
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Player(pub usize);
 
-pub fn handle_mouse_to_move(
+/// Spawns the player at `position`, see [`crate::arena::Arena::player_spawn`].
+pub fn spawn_player(commands: &mut Commands, index: usize, position: Vec2) {
+    commands.spawn((
+        Transform {
+            translation: position.extend(2f32),
+            ..default()
+        },
+        MoveSpeed(130f32),
+        MoveDirection(Vec2::ZERO),
+        MoveTarget {
+            target: Some(position),
+        },
+        Health {
+            current: 1f32,
+            max: 1f32,
+        },
+        Collider { radius: 18f32 },
+        Cooldown {
+            start_time: 0.0,
+            duration: 0.5,
+        },
+        Weapon {
+            fire_rate: 2f32,
+            projectile_count: 1,
+            spread: 0f32,
+            speed: 400f32,
+            damage: 0.25f32,
+            lifetime: 3f32,
+            behaviour: BulletBehaviour::Straight,
+        },
+        Dash {
+            speed_multiplier: 3f32,
+            duration: 0.15,
+            cooldown: 1f32,
+            started_at: None,
+        },
+        Player(index),
+        PlayerInput::default(),
+        TeamIdx::player(index),
+    ));
+}
+
+/// What the player wants to do this tick, filled from the devices or from a [`crate::replay::Replay`].
+///
+/// The simulation only reads this, never the devices directly.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct PlayerInput {
+    /// At most 1 long, shorter when a stick is barely deflected, zero when not moving.
+    pub direction: Vec2,
+    /// World position to shoot at, if firing.
+    pub fire_at: Option<Vec2>,
+    /// Dashes toward `direction` if the [`Dash`] is ready.
+    pub dash: bool,
+}
+
+/// Distance from the player of the point shot at when aiming with buttons or a stick.
+pub const AIM_DISTANCE: f32 = 100f32;
+
+/// Short burst of speed, triggered by [`PlayerInput::dash`].
+#[derive(Component, Debug, Clone)]
+pub struct Dash {
+    pub speed_multiplier: f32,
+    /// Seconds.
+    pub duration: f32,
+    /// Seconds between the starts of two dashes.
+    pub cooldown: f32,
+    /// Simulation seconds when the last dash started.
+    pub started_at: Option<f32>,
+}
+
+impl Dash {
+    pub fn is_dashing(&self, elapsed_seconds: f32) -> bool {
+        self.started_at
+            .is_some_and(|started_at| elapsed_seconds < started_at + self.duration)
+    }
+
+    pub fn is_ready(&self, elapsed_seconds: f32) -> bool {
+        self.started_at.map_or(true, |started_at| {
+            elapsed_seconds >= started_at + self.cooldown
+        })
+    }
+}
+
+/// Where the mouse cursor points at in the world, if it is over the window.
+pub fn cursor_world_position(
+    window: &Window,
+    camera: &Query<(&GlobalTransform, &Camera)>,
+) -> Option<Vec2> {
+    window.cursor_position().zip(camera.iter().next()).and_then(
+        |(position, (camera_transform, camera))| {
+            camera.viewport_to_world_2d(camera_transform, position)
+        },
+    )
+}
+
+/// Reads the fire and aim actions of each player, aiming with the aim buttons,
+/// or the mouse cursor for the player using the mouse.
+pub fn fire_actions(
     q_windows: Query<&Window, With<PrimaryWindow>>,
-    buttons: Res<Input<MouseButton>>,
-    mut q_moves: Query<&mut MoveTarget, With<Player>>,
+    local_players: Res<LocalPlayers>,
+    mut q_inputs: Query<(&Player, &Transform, &mut PlayerInput)>,
     camera: Query<(&GlobalTransform, &Camera)>,
+    mut last_activity: ResMut<LastActivity>,
 ) {
-    if buttons.pressed(MouseButton::Left) {
-        if let Some(position) = q_windows.single().cursor_position() {
-            if let Some((camera_transform, camera)) = camera.iter().next() {
-                let Some(position) = camera.viewport_to_world_2d(camera_transform, position) else {
-                    return;
-                };
-                for mut m in q_moves.iter_mut() {
-                    m.target = Some(position);
-                }
-            }
-        }
-    } else {
-        for mut m in q_moves.iter_mut() {
-            m.target = None;
+    let cursor = cursor_world_position(q_windows.single(), &camera);
+    for (player, transform, mut input) in q_inputs.iter_mut() {
+        let Some((devices, action_state)) = local_players.get(player.0) else {
+            continue;
+        };
+        let aim = action_state.direction(
+            Action::AimUp,
+            Action::AimDown,
+            Action::AimLeft,
+            Action::AimRight,
+        );
+        let mouse_fire = devices.keyboard_mouse
+            && action_state.pressed(Action::Fire)
+            && !action_state.pressed_on_gamepad(Action::Fire);
+        input.fire_at = if aim != Vec2::ZERO {
+            Some(transform.translation.xy() + aim * AIM_DISTANCE)
+        } else if mouse_fire {
+            cursor
+        } else {
+            None
+        };
+        if input.fire_at.is_some() {
+            last_activity.0.reset();
         }
     }
 }
 
-pub fn handle_clicks_to_fire(
+pub fn player_fire(
     mut commands: Commands,
-    time: Res<Time>,
-    q_windows: Query<&Window, With<PrimaryWindow>>,
-    buttons: Res<Input<MouseButton>>,
-    mut q_attackers: Query<(Entity, &Transform, &MoveTarget, &TeamIdx, &Cooldown), With<Player>>,
-    camera: Query<(&GlobalTransform, &Camera)>,
-    mut last_activity: ResMut<LastActivity>,
+    time: Res<SimulationTime>,
+    q_attackers: Query<
+        (
+            Entity,
+            &Transform,
+            &PlayerInput,
+            &TeamIdx,
+            &Weapon,
+            &Cooldown,
+        ),
+        With<Player>,
+    >,
 ) {
-    if buttons.pressed(MouseButton::Left) {
-        if let Some(position) = q_windows.single().cursor_position() {
-            if let Some((camera_transform, camera)) = camera.iter().next() {
-                let Some(position) = camera.viewport_to_world_2d(camera_transform, position) else {
-                    return;
-                };
-                for (entity, transform, _, team, cooldown) in q_attackers.iter_mut() {
-                    let t_position = transform.translation.xy();
-                    // TODO: rework bullet spawn to take place with an event
-                    if commands
-                        .spawn_bullet(
-                            entity,
-                            t_position,
-                            (position - t_position).normalize_or_zero(),
-                            team.clone(),
-                            cooldown,
-                            &time,
-                        )
-                        .is_ok()
-                    {
-                        last_activity.0.reset();
-                        commands.entity(entity).insert(Cooldown {
-                            start_time: time.elapsed_seconds(),
-                            duration: cooldown.duration,
-                        });
-                    }
-                }
-            }
-        }
+    for (entity, transform, input, team, weapon, cooldown) in q_attackers.iter() {
+        let Some(position) = input.fire_at else {
+            continue;
+        };
+        let t_position = transform.translation.xy();
+        // TODO: rework bullet spawn to take place with an event
+        let _ = commands.spawn_bullet(
+            entity,
+            t_position,
+            (position - t_position).normalize_or_zero(),
+            team.clone(),
+            weapon,
+            cooldown,
+            &time,
+        );
     }
 }
@@ -0,0 +1,139 @@
+use bevy::{
+    core_pipeline::{bloom::BloomSettings, tonemapping::Tonemapping},
+    prelude::*,
+    render::camera::ScalingMode,
+};
+use bevy_vector_shapes::prelude::*;
+
+use crate::{
+    actions::ActionsPlugin, arena::Arena, bullets::BulletAudioPlugin,
+    controls_menu::ControlsMenuPlugin, draw::*, editor::EditorPlugin, gamepad::GamepadPlugin,
+    highscores::HighScoresPlugin, hud::HudPlugin, menu::*, movement::move_actions,
+    player::fire_actions, replay::is_playing_back, simulation_time::TimeScale, GameSet,
+};
+
+/// Everything needed to play [`crate::SimulationPlugin`] in a window:
+/// player input, rendering, audio and UI.
+pub struct PresentationPlugin;
+
+impl Plugin for PresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Shape2dPlugin::default());
+        app.add_plugins(BulletAudioPlugin);
+        app.add_plugins(MenuPlugin);
+        app.add_plugins(HighScoresPlugin);
+        app.add_plugins(HudPlugin);
+        app.add_plugins(GamepadPlugin);
+        app.add_plugins(ActionsPlugin);
+        app.add_plugins(ControlsMenuPlugin);
+        app.add_plugins(EditorPlugin);
+        app.add_systems(Startup, setup);
+        app.add_systems(Update, display_arena);
+        app.add_systems(
+            FixedUpdate,
+            (fire_actions, move_actions)
+                .in_set(GameSet::Input)
+                .run_if(not(is_playing_back)),
+        );
+        app.add_systems(Update, replay_speed_keys.run_if(is_playing_back));
+        app.add_systems(
+            Update,
+            draw_arena.run_if(
+                in_state(GameState::Playing)
+                    .or_else(in_state(GameState::Paused))
+                    .or_else(in_state(GameState::GameOver))
+                    .or_else(in_state(GameState::Editor)),
+            ),
+        );
+        app.add_systems(
+            Update,
+            (draw, draw_bullets, draw_health, draw_cooldown, draw_pickups).run_if(
+                in_state(GameState::Playing)
+                    .or_else(in_state(GameState::Paused))
+                    .or_else(in_state(GameState::GameOver)),
+            ),
+        );
+    }
+}
+
+pub fn setup(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle {
+            projection: OrthographicProjection {
+                scaling_mode: ScalingMode::AutoMin {
+                    min_width: 512.0,
+                    min_height: 512.0,
+                },
+                ..default()
+            },
+            camera: Camera {
+                hdr: true, // 1. HDR is required for bloom
+                ..default()
+            },
+            tonemapping: Tonemapping::TonyMcMapface, // 2. Using a tonemapper that desaturates to white is recommended
+            ..default()
+        },
+        BloomSettings::default(), // 3. Enable bloom for the camera
+    ));
+}
+
+/// Background and music of the current [`Arena`].
+#[derive(Component)]
+struct ArenaScenery;
+
+/// Only spawned again when they change, so editing the arena doesn't restart the music.
+fn display_arena(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    arena: Res<Arena>,
+    mut displayed: Local<Option<(Option<String>, Option<String>, Vec2)>>,
+    q_scenery: Query<Entity, With<ArenaScenery>>,
+) {
+    let scenery = (
+        arena.background.clone(),
+        arena.music.clone(),
+        arena.half_size,
+    );
+    if !arena.is_changed() || displayed.as_ref() == Some(&scenery) {
+        return;
+    }
+    *displayed = Some(scenery);
+    for entity in q_scenery.iter() {
+        commands.entity(entity).despawn();
+    }
+    if let Some(background) = &arena.background {
+        commands.spawn((
+            SpriteBundle {
+                texture: asset_server.load(background),
+                sprite: Sprite {
+                    custom_size: Some(arena.half_size * 2f32),
+                    ..default()
+                },
+                ..default()
+            },
+            ArenaScenery,
+        ));
+    }
+    if let Some(music) = &arena.music {
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(music),
+                settings: PlaybackSettings::LOOP,
+            },
+            ArenaScenery,
+        ));
+    }
+}
+
+/// `]` fast-forwards a replay, `[` slows it down and `\` plays it at normal speed again.
+fn replay_speed_keys(keyboard_input: Res<Input<KeyCode>>, mut time_scale: ResMut<TimeScale>) {
+    if keyboard_input.just_pressed(KeyCode::BracketRight) {
+        time_scale.0 = (time_scale.0 * 2f32).min(TimeScale::MAX);
+    }
+    if keyboard_input.just_pressed(KeyCode::BracketLeft) {
+        time_scale.0 = (time_scale.0 / 2f32).max(TimeScale::MIN);
+    }
+    if keyboard_input.just_pressed(KeyCode::Backslash) {
+        time_scale.0 = 1f32;
+    }
+}
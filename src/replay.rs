@@ -0,0 +1,480 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    arena::{Arena, SelectedArena},
+    menu::GameState,
+    movement::MoveSpeed,
+    player::{Player, PlayerInput},
+    rng::GameRng,
+    simulation_time::SimulationTime,
+    GameMode, GameSet, Health, HealthPickup, Lives, SimulationSet, RUN_STARTING_STATES,
+};
+
+/// Records the [`PlayerInput`] of every tick, or feeds them back, depending on [`ReplayMode`].
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayMode>();
+        app.add_event::<EventReplayFinished>();
+        app.configure_set(
+            FixedUpdate,
+            SimulationSet
+                .run_if(|mode: Res<ReplayMode>| has_input_left(&mode) && !is_run_over(&mode)),
+        );
+        app.add_systems(Startup, start_replay);
+        for from in RUN_STARTING_STATES {
+            app.add_systems(
+                OnTransition {
+                    from,
+                    to: GameState::Playing,
+                },
+                mark_run_start,
+            );
+        }
+        app.add_systems(FixedUpdate, record_or_play_inputs.in_set(GameSet::Replay));
+        app.add_systems(Update, (resume_playback, verify_playback));
+        app.add_systems(OnEnter(GameState::GameOver), save_recording);
+    }
+}
+
+/// Everything needed to play a game again: the seed, the mode, the arena and the inputs of each
+/// simulated tick.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    /// The mode can't change while recording or playing back.
+    pub mode: GameMode,
+    /// Name of the [`Arena`], empty for the first one.
+    pub arena: String,
+    /// For each tick, the input of each player by index, the default one when they are dead.
+    pub inputs: Vec<Vec<PlayerInput>>,
+    /// Index in `inputs` of the first tick of each run, a run left from the pause menu ends
+    /// where the next one starts.
+    pub run_starts: Vec<u32>,
+    /// [`simulation_hash`] after the last input, to detect when a playback diverges.
+    pub final_hash: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    BadMode,
+    Truncated,
+    /// A run of identical inputs without any tick.
+    EmptyRun,
+    /// More than [`MAX_TICKS`] ticks.
+    TooLong,
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+const MAGIC: &[u8; 4] = b"CMRP";
+const VERSION: u8 = 1;
+/// Longest replay loaded, a day of play at 60 ticks per second, so a corrupted count can't
+/// exhaust the memory.
+pub const MAX_TICKS: usize = 24 * 60 * 60 * 60;
+
+impl Replay {
+    /// Consecutive identical inputs are stored once with their count, which keeps files small.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        match self.final_hash {
+            Some(hash) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&hash.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        match self.mode {
+            GameMode::Solo => bytes.push(0),
+            GameMode::Coop { players, lives } => {
+                bytes.push(1);
+                bytes.push(players);
+                bytes.push(lives as u8);
+            }
+        }
+        bytes.extend_from_slice(&(self.arena.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(self.arena.as_bytes());
+        bytes.extend_from_slice(&(self.run_starts.len() as u32).to_le_bytes());
+        for start in self.run_starts.iter() {
+            bytes.extend_from_slice(&start.to_le_bytes());
+        }
+        let mut runs: Vec<(u32, &[PlayerInput])> = Vec::new();
+        for inputs in self.inputs.iter() {
+            match runs.last_mut() {
+                Some((count, last)) if *last == inputs.as_slice() => *count += 1,
+                _ => runs.push((1, inputs)),
+            }
+        }
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        // Every tick has an input for each player of the mode.
+        for (count, inputs) in runs {
+            bytes.extend_from_slice(&count.to_le_bytes());
+            for input in inputs {
+                write_input(&mut bytes, input);
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = Reader(bytes);
+        if reader.take::<4>()? != *MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let seed = u64::from_le_bytes(reader.take()?);
+        let final_hash = match reader.u8()? {
+            0 => None,
+            _ => Some(u64::from_le_bytes(reader.take()?)),
+        };
+        let mode = match reader.u8()? {
+            0 => GameMode::Solo,
+            1 => {
+                let players = reader.u8()?;
+                let lives = match reader.u8()? {
+                    0 => Lives::Shared,
+                    1 => Lives::Split,
+                    _ => return Err(ReplayError::BadMode),
+                };
+                if !(2..=GameMode::MAX_PLAYERS as u8).contains(&players) {
+                    return Err(ReplayError::BadMode);
+                }
+                GameMode::Coop { players, lives }
+            }
+            _ => return Err(ReplayError::BadMode),
+        };
+        let length = u16::from_le_bytes(reader.take()?);
+        let arena = String::from_utf8_lossy(reader.bytes(length as usize)?).into_owned();
+        let run_starts = (0..u32::from_le_bytes(reader.take()?))
+            .map(|_| Ok(u32::from_le_bytes(reader.take()?)))
+            .collect::<Result<Vec<_>, ReplayError>>()?;
+        let run_count = u32::from_le_bytes(reader.take()?);
+        let mut inputs = Vec::new();
+        for _ in 0..run_count {
+            let count = u32::from_le_bytes(reader.take()?) as usize;
+            if count == 0 {
+                return Err(ReplayError::EmptyRun);
+            }
+            if inputs.len() + count > MAX_TICKS {
+                return Err(ReplayError::TooLong);
+            }
+            let mut tick = Vec::with_capacity(mode.players());
+            for _ in 0..mode.players() {
+                tick.push(reader.input()?);
+            }
+            inputs.extend(std::iter::repeat(tick).take(count));
+        }
+        Ok(Self {
+            seed,
+            mode,
+            arena,
+            inputs,
+            run_starts,
+            final_hash,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+/// Also used to send the inputs to the other peers, see [`crate::network`].
+pub(crate) fn write_input(bytes: &mut Vec<u8>, input: &PlayerInput) {
+    bytes.extend_from_slice(&input.direction.x.to_le_bytes());
+    bytes.extend_from_slice(&input.direction.y.to_le_bytes());
+    match input.fire_at {
+        Some(fire_at) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&fire_at.x.to_le_bytes());
+            bytes.extend_from_slice(&fire_at.y.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+    bytes.push(input.dash as u8);
+}
+
+pub(crate) struct Reader<'a>(pub &'a [u8]);
+
+impl Reader<'_> {
+    pub fn take<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        if self.0.len() < N {
+            return Err(ReplayError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(taken.try_into().unwrap())
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&[u8], ReplayError> {
+        if self.0.len() < length {
+            return Err(ReplayError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn f32(&mut self) -> Result<f32, ReplayError> {
+        Ok(f32::from_le_bytes(self.take()?))
+    }
+
+    /// Reads what [`write_input`] wrote.
+    pub fn input(&mut self) -> Result<PlayerInput, ReplayError> {
+        let direction = Vec2::new(self.f32()?, self.f32()?);
+        let fire_at = match self.u8()? {
+            0 => None,
+            _ => Some(Vec2::new(self.f32()?, self.f32()?)),
+        };
+        let dash = self.u8()? != 0;
+        Ok(PlayerInput {
+            direction,
+            fire_at,
+            dash,
+        })
+    }
+}
+
+/// Insert it before [`crate::SimulationPlugin`] to record or play back a game.
+#[derive(Resource, Debug, Default)]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    /// The replay is written to `path`, if any, at each game over.
+    Record {
+        replay: Replay,
+        path: Option<PathBuf>,
+    },
+    /// Inputs come from the replay instead of the devices, the simulation stops after the last one.
+    Playback {
+        replay: Replay,
+        cursor: usize,
+        /// Runs started so far.
+        runs: usize,
+        verified: bool,
+    },
+}
+
+impl ReplayMode {
+    pub fn record(path: Option<PathBuf>) -> Self {
+        Self::Record {
+            replay: Replay::default(),
+            path,
+        }
+    }
+
+    pub fn playback(replay: Replay) -> Self {
+        Self::Playback {
+            replay,
+            cursor: 0,
+            runs: 0,
+            verified: false,
+        }
+    }
+}
+
+/// Sent once a playback consumed all its inputs.
+#[derive(Event, Debug)]
+pub struct EventReplayFinished {
+    pub hash: u64,
+    pub expected: Option<u64>,
+}
+
+pub fn is_playing_back(mode: Res<ReplayMode>) -> bool {
+    matches!(*mode, ReplayMode::Playback { .. })
+}
+
+fn has_input_left(mode: &ReplayMode) -> bool {
+    match mode {
+        ReplayMode::Playback { replay, cursor, .. } => *cursor < replay.inputs.len(),
+        _ => true,
+    }
+}
+
+/// The next input is the first of another run, the current one must end before.
+fn is_run_over(mode: &ReplayMode) -> bool {
+    match mode {
+        ReplayMode::Playback {
+            replay,
+            cursor,
+            runs,
+            ..
+        } => replay
+            .run_starts
+            .get(*runs)
+            .is_some_and(|start| *start as usize == *cursor),
+        _ => false,
+    }
+}
+
+/// Hash of the simulated entities, the tick and the random generator state.
+///
+/// Two runs fed the same seed and inputs end up with the same hash.
+pub fn simulation_hash(world: &mut World) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    world.resource::<SimulationTime>().tick.hash(&mut hasher);
+    world
+        .resource::<GameRng>()
+        .rng
+        .get_word_pos()
+        .hash(&mut hasher);
+    let mut q_simulated = world
+        .query_filtered::<(&Transform, Option<&Health>), Or<(With<MoveSpeed>, With<HealthPickup>)>>(
+        );
+    for (transform, health) in q_simulated.iter(world) {
+        for value in transform.translation.to_array() {
+            value.to_bits().hash(&mut hasher);
+        }
+        if let Some(health) = health {
+            health.current.to_bits().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn start_replay(
+    mut mode: ResMut<ReplayMode>,
+    mut rng: ResMut<GameRng>,
+    mut game_mode: ResMut<GameMode>,
+    mut selected_arena: ResMut<SelectedArena>,
+) {
+    match &mut *mode {
+        ReplayMode::Off => {}
+        ReplayMode::Record { replay, .. } => replay.seed = rng.seed(),
+        ReplayMode::Playback { replay, .. } => {
+            *rng = GameRng::from_seed(replay.seed);
+            *game_mode = replay.mode;
+            selected_arena.0 = replay.arena.clone();
+        }
+    }
+}
+
+pub(crate) fn record_or_play_inputs(
+    mut mode: ResMut<ReplayMode>,
+    game_mode: Res<GameMode>,
+    arena: Res<Arena>,
+    mut q_inputs: Query<(&Player, &mut PlayerInput)>,
+) {
+    match &mut *mode {
+        ReplayMode::Off => {}
+        ReplayMode::Record { replay, .. } => {
+            replay.mode = *game_mode;
+            if replay.arena != arena.name {
+                replay.arena = arena.name.clone();
+            }
+            let mut inputs = vec![PlayerInput::default(); game_mode.players()];
+            for (player, input) in q_inputs.iter() {
+                inputs[player.0] = *input;
+            }
+            replay.inputs.push(inputs);
+        }
+        ReplayMode::Playback { replay, cursor, .. } => {
+            for (player, mut input) in q_inputs.iter_mut() {
+                if let Some(recorded) = replay.inputs[*cursor].get(player.0) {
+                    *input = *recorded;
+                }
+            }
+            *cursor += 1;
+        }
+    }
+}
+
+fn mark_run_start(mut mode: ResMut<ReplayMode>) {
+    match &mut *mode {
+        ReplayMode::Off => {}
+        ReplayMode::Record { replay, .. } => replay.run_starts.push(replay.inputs.len() as u32),
+        ReplayMode::Playback { runs, .. } => *runs += 1,
+    }
+}
+
+/// Starts the runs without waiting for the menus, and leaves the ones left from the pause menu,
+/// like the recording did.
+fn resume_playback(
+    mode: Res<ReplayMode>,
+    game_state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if next_state.0.is_some() || !has_input_left(&mode) {
+        return;
+    }
+    match game_state.get() {
+        GameState::MainMenu | GameState::GameOver => next_state.set(GameState::Playing),
+        GameState::Playing if is_run_over(&mode) => next_state.set(GameState::MainMenu),
+        _ => {}
+    }
+}
+
+fn verify_playback(world: &mut World) {
+    let expected = match world.resource::<ReplayMode>() {
+        ReplayMode::Playback {
+            replay,
+            cursor,
+            verified: false,
+            ..
+        } if *cursor == replay.inputs.len() => replay.final_hash,
+        _ => return,
+    };
+    let hash = simulation_hash(world);
+    match expected {
+        Some(expected) if expected != hash => {
+            warn!("Replay diverged: final hash {hash:x}, expected {expected:x}")
+        }
+        Some(_) => info!("Replay matches, final hash {hash:x}"),
+        None => info!("Replay finished, final hash {hash:x}"),
+    }
+    world.send_event(EventReplayFinished { hash, expected });
+    if let ReplayMode::Playback { verified, .. } = &mut *world.resource_mut::<ReplayMode>() {
+        *verified = true;
+    }
+}
+
+fn save_recording(world: &mut World) {
+    if !matches!(
+        *world.resource::<ReplayMode>(),
+        ReplayMode::Record { path: Some(_), .. }
+    ) {
+        return;
+    }
+    let hash = simulation_hash(world);
+    let ReplayMode::Record {
+        replay,
+        path: Some(path),
+    } = &mut *world.resource_mut::<ReplayMode>()
+    else {
+        return;
+    };
+    replay.final_hash = Some(hash);
+    match replay.save(&*path) {
+        Ok(()) => info!("Replay saved to {}", path.display()),
+        Err(error) => warn!("Couldn't save replay to {}: {error:?}", path.display()),
+    }
+}
@@ -0,0 +1,33 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// The only source of randomness the simulation is allowed to use.
+///
+/// A given seed and input stream always play out the same way, so never reach for
+/// `rand::thread_rng()` in gameplay systems.
+#[derive(Resource, Clone)]
+pub struct GameRng {
+    seed: u64,
+    pub rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// Picks a random seed, insert [`GameRng::from_seed`] before the simulation to choose it.
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::from_seed(rand::random())
+    }
+}
@@ -0,0 +1,322 @@
+use std::any::Any;
+
+use bevy::{
+    ecs::world::{EntityMut, EntityRef},
+    prelude::*,
+    utils::HashMap,
+};
+
+use crate::{
+    ai::Ai,
+    archetypes::{Appearance, DropTable},
+    arena::PickupSpawner,
+    behaviour::{Behaviour, Brain},
+    boss::Boss,
+    bullets::{Bullet, BulletOwner, EventBulletSpawn, Weapon},
+    despawn_after::DespawnAfter,
+    movement::{MoveDirection, MoveSpeed, MoveTarget},
+    player::{Dash, Player, PlayerInput},
+    rng::GameRng,
+    simulation_time::SimulationTime,
+    spatial_hash::Collider,
+    stats::{RunStats, ScoreValue},
+    steering::Steering,
+    waves::{SpawnDirector, WaveMember},
+    Cooldown, EventTryApplyDamages, Health, HealthPickup, PendingRespawns, RemainingLives,
+    RemoveOnRespawn, TeamIdx,
+};
+
+/// Saves the state of the simulation between two ticks, and restores it later, so an online session
+/// can go back in time and simulate again with the inputs it mispredicted, see [`crate::network`].
+///
+/// Every component, resource and cross-tick event of the simulation must be registered here,
+/// anything else isn't rolled back and would desync the peers.
+pub struct RollbackPlugin;
+
+impl Plugin for RollbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RollbackRegistry>()
+            .init_resource::<PresentedTick>()
+            .add_systems(First, update_presented_tick)
+            .register_rollback_component::<Transform>()
+            .register_rollback_component::<MoveSpeed>()
+            .register_rollback_component::<MoveDirection>()
+            .register_rollback_component::<MoveTarget>()
+            .register_rollback_component::<Health>()
+            .register_rollback_component::<Collider>()
+            .register_rollback_component::<Cooldown>()
+            .register_rollback_component::<Weapon>()
+            .register_rollback_component::<Dash>()
+            .register_rollback_component::<Player>()
+            .register_rollback_component::<PlayerInput>()
+            .register_rollback_component::<TeamIdx>()
+            .register_mapped_rollback_component::<Bullet>()
+            .register_mapped_rollback_component::<BulletOwner>()
+            .register_rollback_component::<DespawnAfter>()
+            .register_rollback_component::<Ai>()
+            .register_rollback_component::<Steering>()
+            .register_rollback_component::<Behaviour>()
+            .register_rollback_component::<Brain>()
+            .register_rollback_component::<Boss>()
+            .register_rollback_component::<Appearance>()
+            .register_rollback_component::<DropTable>()
+            .register_rollback_component::<ScoreValue>()
+            .register_rollback_component::<WaveMember>()
+            .register_rollback_component::<HealthPickup>()
+            .register_rollback_component::<PickupSpawner>()
+            .register_rollback_component::<RemoveOnRespawn>()
+            .register_rollback_resource::<SimulationTime>()
+            .register_rollback_resource::<GameRng>()
+            .register_rollback_resource::<SpawnDirector>()
+            .register_rollback_resource::<RunStats>()
+            .register_rollback_resource::<RemainingLives>()
+            .register_rollback_resource::<PendingRespawns>()
+            .register_rollback_event::<EventTryApplyDamages>()
+            .register_rollback_event::<EventBulletSpawn>();
+    }
+}
+
+/// The last tick simulated before this frame.
+///
+/// A rollback simulates ticks up to it again, and sends their events again: the presentation skips
+/// the events of these ticks, it already played their sounds and showed their banners.
+#[derive(Resource, Debug, Default)]
+pub struct PresentedTick(pub u64);
+
+impl PresentedTick {
+    /// The events sent in `tick` weren't presented yet.
+    pub fn is_new(&self, tick: u64) -> bool {
+        tick > self.0
+    }
+}
+
+fn update_presented_tick(time: Res<SimulationTime>, mut presented: ResMut<PresentedTick>) {
+    presented.0 = time.tick;
+}
+
+/// Entities of the simulation: what a run despawns when it starts.
+pub type Simulated = Or<(With<Player>, With<RemoveOnRespawn>)>;
+
+/// Restored entities are spawned again, so they get new ids.
+pub type EntityMap = HashMap<Entity, Entity>;
+
+/// Implemented by what refers to simulated entities, to follow them when they are restored.
+pub trait MapRollbackEntities {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+/// Entities which weren't part of the snapshot are dead, their id stays as is and never matches anything.
+fn mapped(entity: Entity, map: &EntityMap) -> Entity {
+    map.get(&entity).copied().unwrap_or(entity)
+}
+
+impl MapRollbackEntities for BulletOwner {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.entity = mapped(self.entity, map);
+    }
+}
+
+impl MapRollbackEntities for Bullet {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for hit in self.hits.iter_mut() {
+            *hit = mapped(*hit, map);
+        }
+    }
+}
+
+impl MapRollbackEntities for EventBulletSpawn {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.shooter = mapped(self.shooter, map);
+    }
+}
+
+impl MapRollbackEntities for EventTryApplyDamages {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.attacker = mapped(self.attacker, map);
+        self.victim = mapped(self.victim, map);
+    }
+}
+
+type Saved = Box<dyn Any + Send + Sync>;
+
+struct RollbackComponent {
+    save: fn(&EntityRef) -> Option<Saved>,
+    restore: fn(&mut EntityMut, &Saved, &EntityMap),
+}
+
+struct RollbackResource {
+    save: fn(&World) -> Option<Saved>,
+    restore: fn(&mut World, &Saved, &EntityMap),
+}
+
+/// How to save and restore each registered type, filled by [`RollbackAppExt`].
+#[derive(Resource, Default)]
+pub struct RollbackRegistry {
+    components: Vec<RollbackComponent>,
+    resources: Vec<RollbackResource>,
+}
+
+pub trait RollbackAppExt {
+    fn register_rollback_component<T: Component + Clone>(&mut self) -> &mut Self;
+    /// For the components referring to other entities.
+    fn register_mapped_rollback_component<T: Component + Clone + MapRollbackEntities>(
+        &mut self,
+    ) -> &mut Self;
+    /// Events sent by a tick for the next one to read, the other ones are read within the tick.
+    fn register_rollback_event<T: Event + Clone + MapRollbackEntities>(&mut self) -> &mut Self;
+    fn register_rollback_resource<T: Resource + Clone>(&mut self) -> &mut Self;
+}
+
+impl RollbackAppExt for App {
+    fn register_rollback_component<T: Component + Clone>(&mut self) -> &mut Self {
+        self.world
+            .resource_mut::<RollbackRegistry>()
+            .components
+            .push(RollbackComponent {
+                save: |entity| {
+                    entity
+                        .get::<T>()
+                        .map(|component| Box::new(component.clone()) as Saved)
+                },
+                restore: |entity, saved, _| {
+                    entity.insert(saved.downcast_ref::<T>().unwrap().clone());
+                },
+            });
+        self
+    }
+
+    fn register_mapped_rollback_component<T: Component + Clone + MapRollbackEntities>(
+        &mut self,
+    ) -> &mut Self {
+        self.world
+            .resource_mut::<RollbackRegistry>()
+            .components
+            .push(RollbackComponent {
+                save: |entity| {
+                    entity
+                        .get::<T>()
+                        .map(|component| Box::new(component.clone()) as Saved)
+                },
+                restore: |entity, saved, map| {
+                    let mut component = saved.downcast_ref::<T>().unwrap().clone();
+                    component.map_entities(map);
+                    entity.insert(component);
+                },
+            });
+        self
+    }
+
+    fn register_rollback_event<T: Event + Clone + MapRollbackEntities>(&mut self) -> &mut Self {
+        self.world
+            .resource_mut::<RollbackRegistry>()
+            .resources
+            .push(RollbackResource {
+                save: |world| {
+                    let events = world.get_resource::<Events<T>>()?;
+                    let pending = events
+                        .iter_current_update_events()
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    Some(Box::new(pending))
+                },
+                restore: |world, saved, map| {
+                    let mut events = world.resource_mut::<Events<T>>();
+                    events.clear();
+                    for event in saved.downcast_ref::<Vec<T>>().unwrap() {
+                        let mut event = event.clone();
+                        event.map_entities(map);
+                        events.send(event);
+                    }
+                },
+            });
+        self
+    }
+
+    fn register_rollback_resource<T: Resource + Clone>(&mut self) -> &mut Self {
+        self.world
+            .resource_mut::<RollbackRegistry>()
+            .resources
+            .push(RollbackResource {
+                save: |world| {
+                    world
+                        .get_resource::<T>()
+                        .map(|resource| Box::new(resource.clone()) as Saved)
+                },
+                restore: |world, saved, _| {
+                    world.insert_resource(saved.downcast_ref::<T>().unwrap().clone());
+                },
+            });
+        self
+    }
+}
+
+/// Added while a restored entity gets its components back one by one,
+/// so it never goes through an archetype a simulated entity could have.
+#[derive(Component)]
+struct Restoring;
+
+/// State of the simulation between two ticks.
+pub struct Snapshot {
+    /// In query order, which restoring keeps so the systems iterate them the same way.
+    entities: Vec<(Entity, Vec<(usize, Saved)>)>,
+    resources: Vec<Option<Saved>>,
+}
+
+impl Snapshot {
+    pub fn save(world: &mut World) -> Self {
+        let registry = world.resource::<RollbackRegistry>();
+        let resources = registry
+            .resources
+            .iter()
+            .map(|resource| (resource.save)(world))
+            .collect();
+        let mut q_simulated = world.query_filtered::<Entity, Simulated>();
+        let registry = world.resource::<RollbackRegistry>();
+        let entities = q_simulated
+            .iter(world)
+            .map(|entity| {
+                let entity_ref = world.entity(entity);
+                let components = registry
+                    .components
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, component)| {
+                        (component.save)(&entity_ref).map(|saved| (index, saved))
+                    })
+                    .collect();
+                (entity, components)
+            })
+            .collect();
+        Self {
+            entities,
+            resources,
+        }
+    }
+
+    /// Replaces every simulated entity and registered resource by the saved ones.
+    pub fn restore(&self, world: &mut World) {
+        let mut q_simulated = world.query_filtered::<Entity, Simulated>();
+        for entity in q_simulated.iter(world).collect::<Vec<_>>() {
+            world.despawn(entity);
+        }
+        let map = self
+            .entities
+            .iter()
+            .map(|(saved, _)| (*saved, world.spawn(Restoring).id()))
+            .collect::<EntityMap>();
+        world.resource_scope(|world, registry: Mut<RollbackRegistry>| {
+            for (saved, components) in self.entities.iter() {
+                let mut entity = world.entity_mut(map[saved]);
+                for (index, component) in components {
+                    (registry.components[*index].restore)(&mut entity, component, &map);
+                }
+                entity.remove::<Restoring>();
+            }
+            for (resource, saved) in registry.resources.iter().zip(self.resources.iter()) {
+                if let Some(saved) = saved {
+                    (resource.restore)(world, saved, &map);
+                }
+            }
+        });
+    }
+}
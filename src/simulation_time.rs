@@ -0,0 +1,53 @@
+use bevy::{prelude::*, utils::Duration};
+
+/// Duration of a simulation tick, gameplay systems run in `FixedUpdate` at this rate.
+pub const SIMULATION_TIMESTEP: f32 = 1f32 / 60f32;
+
+/// Time as seen by the simulation, only advancing when a tick is simulated.
+///
+/// Gameplay systems use this instead of [`Time`] so their outcome doesn't depend on the frame rate.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct SimulationTime {
+    pub tick: u64,
+    pub delta: Duration,
+}
+
+impl SimulationTime {
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.tick as f32 * self.delta_seconds()
+    }
+}
+
+/// Speed of the simulation relative to real time, below 1 for slow motion, above to fast-forward.
+///
+/// It changes how often ticks run, not their length, so the simulation stays deterministic.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct TimeScale(pub f32);
+
+impl TimeScale {
+    pub const MIN: f32 = 1f32 / 16f32;
+    pub const MAX: f32 = 16f32;
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self(1f32)
+    }
+}
+
+pub fn advance_simulation_time(mut time: ResMut<SimulationTime>) {
+    time.tick += 1;
+    time.delta = Duration::from_secs_f32(SIMULATION_TIMESTEP);
+}
+
+pub fn apply_time_scale(time_scale: Res<TimeScale>, mut fixed_time: ResMut<FixedTime>) {
+    if time_scale.is_changed() {
+        fixed_time.period = Duration::from_secs_f32(
+            SIMULATION_TIMESTEP / time_scale.0.clamp(TimeScale::MIN, TimeScale::MAX),
+        );
+    }
+}
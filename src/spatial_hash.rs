@@ -0,0 +1,91 @@
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap};
+
+/// Circle used by the collision systems, centered on the entity [`Transform`].
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Collider {
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpatialEntry {
+    pub entity: Entity,
+    pub position: Vec2,
+    pub radius: f32,
+}
+
+/// Uniform grid of every entity with a [`Collider`], rebuilt each tick by [`rebuild_spatial_hash`].
+///
+/// Entities are stored in the cell of their center only, queries are widened by the largest
+/// collider radius instead, so an entity is never returned twice.
+#[derive(Resource, Debug)]
+pub struct SpatialHash {
+    cell_size: f32,
+    max_radius: f32,
+    cells: HashMap<IVec2, Vec<SpatialEntry>>,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(64f32)
+    }
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            max_radius: 0f32,
+            cells: HashMap::default(),
+        }
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Removes every entry, keeping the allocations of the cells used since the last clear.
+    pub fn clear(&mut self) {
+        self.max_radius = 0f32;
+        self.cells.retain(|_, entries| {
+            let used = !entries.is_empty();
+            entries.clear();
+            used
+        });
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec2, radius: f32) {
+        self.max_radius = self.max_radius.max(radius);
+        self.cells
+            .entry(self.cell(position))
+            .or_default()
+            .push(SpatialEntry {
+                entity,
+                position,
+                radius,
+            });
+    }
+
+    /// Entries whose collider overlaps the circle, in a deterministic order.
+    pub fn query(&self, position: Vec2, radius: f32) -> impl Iterator<Item = &SpatialEntry> + '_ {
+        let reach = Vec2::splat(radius + self.max_radius);
+        let min = self.cell(position - reach);
+        let max = self.cell(position + reach);
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter(move |entry| {
+                entry.position.distance_squared(position) < (entry.radius + radius).powi(2)
+            })
+    }
+}
+
+pub fn rebuild_spatial_hash(
+    mut spatial_hash: ResMut<SpatialHash>,
+    q_colliders: Query<(Entity, &Transform, &Collider)>,
+) {
+    spatial_hash.clear();
+    for (entity, transform, collider) in q_colliders.iter() {
+        spatial_hash.insert(entity, transform.translation.xy(), collider.radius);
+    }
+}
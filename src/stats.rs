@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+
+use crate::{
+    bullets::EventBulletSpawn, handle_player_deaths, player::Player,
+    simulation_time::SimulationTime, EventDamaged, EventDied, GameSet,
+};
+
+/// Keeps the [`RunStats`] of the current run, until the next one starts.
+pub struct RunStatsPlugin;
+
+impl Plugin for RunStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunStats>().add_systems(
+            FixedUpdate,
+            update_run_stats
+                .after(handle_player_deaths)
+                .in_set(GameSet::Damages),
+        );
+    }
+}
+
+/// Points scored when killing this entity, before the combo multiplier.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ScoreValue(pub u32);
+
+#[derive(Resource, Debug, Clone)]
+pub struct RunStats {
+    pub score: u64,
+    pub kills: u32,
+    /// Projectiles fired by the players.
+    pub shots_fired: u32,
+    /// Projectiles of the players which dealt damages, once each even when piercing or exploding.
+    pub shots_hit: u32,
+    pub damage_taken: f32,
+    /// Seconds until the last player died.
+    pub survival_time: f32,
+    /// Multiplies the points of kills, see [`RunStats::COMBO_STEP`].
+    pub combo: f32,
+    /// Seconds since the last kill.
+    pub since_last_kill: f32,
+    /// Every player is dead, the stats stay as they are until the next run starts.
+    pub finished: bool,
+}
+
+impl Default for RunStats {
+    fn default() -> Self {
+        Self {
+            score: 0,
+            kills: 0,
+            shots_fired: 0,
+            shots_hit: 0,
+            damage_taken: 0f32,
+            survival_time: 0f32,
+            combo: 1f32,
+            since_last_kill: 0f32,
+            finished: false,
+        }
+    }
+}
+
+impl RunStats {
+    /// Added to the combo by each kill.
+    pub const COMBO_STEP: f32 = 0.25;
+    pub const COMBO_MAX: f32 = 4f32;
+    /// Seconds without a kill before the combo starts to decay.
+    pub const COMBO_GRACE: f32 = 2f32;
+    /// Combo lost per second once decaying.
+    pub const COMBO_DECAY: f32 = 0.5;
+
+    /// Ratio of fired projectiles which hit something, between 0 and 1.
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            return 0f32;
+        }
+        self.shots_hit as f32 / self.shots_fired as f32
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "Score: {}\nKills: {}\nAccuracy: {:.0}%\nDamage taken: {:.2}\nSurvived: {:.1}s",
+            self.score,
+            self.kills,
+            self.accuracy() * 100f32,
+            self.damage_taken,
+            self.survival_time
+        )
+    }
+}
+
+fn update_run_stats(
+    time: Res<SimulationTime>,
+    mut stats: ResMut<RunStats>,
+    mut events_bullets: EventReader<EventBulletSpawn>,
+    mut events_damaged: EventReader<EventDamaged>,
+    mut events_died: EventReader<EventDied>,
+    q_players: Query<(), With<Player>>,
+    q_values: Query<&ScoreValue>,
+) {
+    for ev in events_bullets.iter() {
+        if q_players.contains(ev.shooter) {
+            stats.shots_fired += ev.projectiles;
+        }
+    }
+    for ev in events_damaged.iter() {
+        if ev.first_hit && q_players.contains(ev.attacker) {
+            stats.shots_hit += 1;
+        }
+        if q_players.contains(ev.victim) {
+            stats.damage_taken += ev.amount;
+            stats.combo = 1f32;
+        }
+    }
+    for ev in events_died.iter() {
+        if q_players.contains(ev.attacker) && !q_players.contains(ev.victim) {
+            // Despawns are deferred to the end of the tick, the victim is still there.
+            let points = q_values.get(ev.victim).map_or(0, |value| value.0);
+            stats.score += (points as f32 * stats.combo).round() as u64;
+            stats.kills += 1;
+            stats.combo = (stats.combo + RunStats::COMBO_STEP).min(RunStats::COMBO_MAX);
+            stats.since_last_kill = 0f32;
+        }
+    }
+    if stats.finished {
+        return;
+    }
+    let delta = time.delta_seconds();
+    stats.survival_time += delta;
+    stats.since_last_kill += delta;
+    if stats.since_last_kill > RunStats::COMBO_GRACE {
+        stats.combo = (stats.combo - RunStats::COMBO_DECAY * delta).max(1f32);
+    }
+}
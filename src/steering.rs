@@ -0,0 +1,200 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    ai::{nearest_player, Ai},
+    behaviour::{Brain, Tactic},
+    movement::MoveDirection,
+    navigation::FlowField,
+    player::Player,
+    simulation_time::SimulationTime,
+    spatial_hash::SpatialHash,
+};
+
+/// How an AI moves, declared by its [`crate::archetypes::EnemyArchetype`].
+///
+/// Each behaviour gives a desired velocity, as a fraction of the [`crate::movement::MoveSpeed`],
+/// they are summed with their weight and the [`MoveDirection`] turns toward the result.
+/// The [`Tactic`] of the AI picks the behaviours relative to the player.
+#[derive(Component, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Steering {
+    /// Weight of going toward the nearest player.
+    pub seek: f32,
+    /// Weight of going away from the nearest player, within `flee_radius`.
+    pub flee: f32,
+    /// Weight of circling around the nearest player, and of running away from it when retreating.
+    pub orbit: f32,
+    /// Weight of keeping away from the other AIs within `separation_radius`.
+    pub separation: f32,
+    /// Weight of going the same way as the other AIs within `alignment_radius`.
+    pub alignment: f32,
+    /// Distance from the nearest player the AI circles at.
+    pub orbit_radius: f32,
+    pub flee_radius: f32,
+    pub separation_radius: f32,
+    pub alignment_radius: f32,
+    /// Fraction of the speed the velocity can change by each second, lower turns wider.
+    pub acceleration: f32,
+    /// Picked when spawned, so the crowd doesn't all turn the same way.
+    #[serde(skip)]
+    pub clockwise: bool,
+}
+
+impl Default for Steering {
+    fn default() -> Self {
+        Self {
+            seek: 1f32,
+            flee: 1.5f32,
+            orbit: 0.8f32,
+            separation: 2f32,
+            alignment: 0.3f32,
+            orbit_radius: 200f32,
+            flee_radius: 100f32,
+            separation_radius: 40f32,
+            alignment_radius: 80f32,
+            acceleration: 3f32,
+            clockwise: false,
+        }
+    }
+}
+
+impl Steering {
+    /// Velocity toward the player until `radius`, slowing down when getting close to it.
+    fn seek(&self, to_player: Vec2, radius: f32) -> Vec2 {
+        let distance = to_player.length();
+        let remaining = ((distance - radius) / radius.max(1f32)).clamp(0f32, 1f32);
+        to_player.normalize_or_zero() * remaining
+    }
+
+    /// Velocity away from the player, stronger the closer it is.
+    fn flee(&self, to_player: Vec2) -> Vec2 {
+        let distance = to_player.length();
+        if distance >= self.flee_radius {
+            return Vec2::ZERO;
+        }
+        -to_player.normalize_or_zero() * (1f32 - distance / self.flee_radius)
+    }
+
+    /// Velocity around the player.
+    fn orbit(&self, to_player: Vec2) -> Vec2 {
+        let tangent = to_player.normalize_or_zero().perp();
+        match self.clockwise {
+            true => -tangent,
+            false => tangent,
+        }
+    }
+
+    /// Fades the orbit out far from `orbit_radius`, so seeking takes over.
+    fn orbit_closeness(&self, to_player: Vec2) -> f32 {
+        let distance = to_player.length();
+        (1f32 - (distance - self.orbit_radius).abs() / self.orbit_radius).max(0f32)
+    }
+
+    /// Velocity away from the neighbours, stronger the closer they are.
+    fn separation(&self, position: Vec2, neighbours: &[(Vec2, Vec2)]) -> Vec2 {
+        neighbours
+            .iter()
+            .filter_map(|(neighbour, _)| {
+                let away = position - *neighbour;
+                let distance = away.length();
+                (distance < self.separation_radius)
+                    .then(|| away.normalize_or_zero() * (1f32 - distance / self.separation_radius))
+            })
+            .sum::<Vec2>()
+            .clamp_length_max(1f32)
+    }
+
+    /// Average velocity of the neighbours.
+    fn alignment(&self, position: Vec2, neighbours: &[(Vec2, Vec2)]) -> Vec2 {
+        let (sum, count) = neighbours
+            .iter()
+            .filter(|(neighbour, _)| neighbour.distance(position) < self.alignment_radius)
+            .fold((Vec2::ZERO, 0), |(sum, count), (_, velocity)| {
+                (sum + *velocity, count + 1)
+            });
+        match count {
+            0 => Vec2::ZERO,
+            _ => sum / count as f32,
+        }
+    }
+
+    /// Weighted sum of the behaviours, at most 1 long.
+    ///
+    /// With a `detour`, the player is behind an obstacle: going around it comes first,
+    /// unless retreating, see [`FlowField::detour`].
+    pub fn desired_velocity(
+        &self,
+        tactic: Tactic,
+        position: Vec2,
+        player: Option<(Vec2, Option<Vec2>)>,
+        neighbours: &[(Vec2, Vec2)],
+    ) -> Vec2 {
+        let mut desired = self.separation * self.separation(position, neighbours)
+            + self.alignment * self.alignment(position, neighbours);
+        if let Some((player, detour)) = player {
+            let to_player = player - position;
+            desired += match (tactic, detour) {
+                (Tactic::Retreat, _) => -self.flee * to_player.normalize_or_zero(),
+                (_, Some(detour)) => self.seek * detour.normalize_or_zero(),
+                (Tactic::Approach, None) => {
+                    self.seek * self.seek(to_player, self.flee_radius)
+                        + self.flee * self.flee(to_player)
+                }
+                (Tactic::KeepDistance, None) => {
+                    self.seek * self.seek(to_player, self.orbit_radius)
+                        + self.flee * self.flee(to_player)
+                        + self.orbit * self.orbit_closeness(to_player) * self.orbit(to_player)
+                }
+                (Tactic::Strafe, None) => {
+                    self.orbit * self.orbit(to_player) + self.flee * self.flee(to_player)
+                }
+            };
+        }
+        desired.clamp_length_max(1f32)
+    }
+}
+
+/// Turns the [`MoveDirection`] of each AI toward its [`Steering`] desired velocity.
+///
+/// Neighbours come from the [`SpatialHash`], which must be up to date: it is rebuilt right before,
+/// as the one from the previous tick may be from a timeline a rollback discarded.
+/// The [`FlowField`] is kept up to date the same way.
+pub fn steer_ais(
+    time: Res<SimulationTime>,
+    spatial_hash: Res<SpatialHash>,
+    flow_field: Res<FlowField>,
+    q_players: Query<&Transform, With<Player>>,
+    mut q_ais: Query<(Entity, &Transform, &Steering, &Brain, &mut MoveDirection), With<Ai>>,
+) {
+    let players = q_players
+        .iter()
+        .map(|transform| transform.translation.xy())
+        .collect::<Vec<_>>();
+    let mut velocities = Vec::new();
+    for (entity, transform, steering, brain, _) in q_ais.iter() {
+        let position = transform.translation.xy();
+        let reach = steering.separation_radius.max(steering.alignment_radius);
+        let neighbours = spatial_hash
+            .query(position, reach)
+            .filter(|entry| entry.entity != entity)
+            .filter_map(|entry| {
+                let (_, _, _, _, velocity) = q_ais.get(entry.entity).ok()?;
+                Some((entry.position, velocity.0))
+            })
+            .collect::<Vec<_>>();
+        let player = nearest_player(position, &players)
+            .map(|player| (player, flow_field.detour(position, player)));
+        let desired = steering.desired_velocity(brain.tactic, position, player, &neighbours);
+        velocities.push((entity, desired));
+    }
+    // Applied once every AI looked at its neighbours, so none sees the others already turned.
+    for (entity, desired) in velocities {
+        let Ok((_, _, steering, _, mut velocity)) = q_ais.get_mut(entity) else {
+            continue;
+        };
+        let max_change = steering.acceleration * time.delta_seconds();
+        velocity.0 = (velocity.0 + (desired - velocity.0).clamp_length_max(max_change))
+            .clamp_length_max(1f32);
+    }
+}
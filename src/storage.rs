@@ -0,0 +1,50 @@
+//! Small text files the game keeps between sessions, like the high scores and the bindings.
+
+/// `<name>.ron` next to the game on native.
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use bevy::prelude::*;
+
+    fn path(name: &str) -> String {
+        format!("{name}.ron")
+    }
+
+    pub fn read(name: &str) -> Option<String> {
+        std::fs::read_to_string(path(name)).ok()
+    }
+
+    pub fn write(name: &str, contents: &str) {
+        if let Err(error) = std::fs::write(path(name), contents) {
+            warn!("Couldn't save {}: {error}", path(name));
+        }
+    }
+}
+
+/// The `localStorage` of the page on the web.
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    use bevy::prelude::*;
+
+    fn key(name: &str) -> String {
+        format!("circles_madness.{name}")
+    }
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn read(name: &str) -> Option<String> {
+        local_storage()?.get_item(&key(name)).ok()?
+    }
+
+    pub fn write(name: &str, contents: &str) {
+        if local_storage()
+            .and_then(|storage| storage.set_item(&key(name), contents).ok())
+            .is_none()
+        {
+            warn!("Couldn't save {} to the local storage", key(name));
+        }
+    }
+}
+
+pub use platform::{read, write};
@@ -6,5 +6,83 @@ pub fn move_towards(from: Vec2, to: Vec2, max_distance: f32) -> Vec2 {
     if total_distance <= max_distance {
         return to;
     }
-    return from + (to_target / total_distance) * max_distance;
+    from + (to_target / total_distance) * max_distance
+}
+
+/// Where to shoot from `shooter` a projectile going at `speed`, to hit a target at `target`
+/// keeping its `velocity`. `None` when the target outruns the projectile.
+pub fn intercept(shooter: Vec2, target: Vec2, velocity: Vec2, speed: f32) -> Option<Vec2> {
+    let to_target = target - shooter;
+    // |to_target + velocity * t| = speed * t, solved for the earliest positive t.
+    let a = velocity.length_squared() - speed * speed;
+    let b = 2f32 * to_target.dot(velocity);
+    let c = to_target.length_squared();
+    let time = if a.abs() < f32::EPSILON {
+        (b < 0f32).then(|| -c / b)?
+    } else {
+        let discriminant = b * b - 4f32 * a * c;
+        if discriminant < 0f32 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        [(-b - root) / (2f32 * a), (-b + root) / (2f32 * a)]
+            .into_iter()
+            .filter(|time| *time >= 0f32)
+            .reduce(f32::min)?
+    };
+    Some(target + velocity * time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The projectile and the target reach `aim` at the same time.
+    fn assert_hits(shooter: Vec2, target: Vec2, velocity: Vec2, speed: f32) {
+        let aim = intercept(shooter, target, velocity, speed).expect("no interception");
+        let time = shooter.distance(aim) / speed;
+        let target_then = target + velocity * time;
+        assert!(
+            target_then.distance(aim) < 1e-3,
+            "aimed at {aim}, the target is at {target_then}"
+        );
+    }
+
+    #[test]
+    fn stationary_target_is_aimed_at_directly() {
+        let target = Vec2::new(30f32, -40f32);
+        assert_eq!(
+            intercept(Vec2::ZERO, target, Vec2::ZERO, 100f32),
+            Some(target)
+        );
+    }
+
+    #[test]
+    fn target_crossing_the_shot_is_led() {
+        let (shooter, target, velocity) = (Vec2::ZERO, Vec2::new(200f32, -50f32), Vec2::Y * 60f32);
+        assert_hits(shooter, target, velocity, 300f32);
+        assert!(intercept(shooter, target, velocity, 300f32).unwrap().y > target.y);
+    }
+
+    #[test]
+    fn target_as_fast_as_the_shot_is_caught_coming_closer() {
+        assert_hits(
+            Vec2::ZERO,
+            Vec2::X * 100f32,
+            Vec2::new(-30f32, 40f32),
+            50f32,
+        );
+        assert_eq!(
+            intercept(Vec2::ZERO, Vec2::X * 100f32, Vec2::new(30f32, 40f32), 50f32),
+            None
+        );
+    }
+
+    #[test]
+    fn target_outrunning_the_shot_is_missed() {
+        assert_eq!(
+            intercept(Vec2::ZERO, Vec2::X * 100f32, Vec2::X * 200f32, 100f32),
+            None
+        );
+    }
 }
@@ -0,0 +1,252 @@
+use bevy::{
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+};
+use bevy_asset_loader::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use crate::{
+    ai::{spawn_enemy, Ai},
+    archetypes::{EnemyArchetype, EnemyAssets},
+    arena::Arena,
+    menu::GameState,
+    rng::GameRng,
+    ron_asset::RonAssetLoader,
+    simulation_time::SimulationTime,
+    GameDef, SimulationEventAppExt,
+};
+
+/// Spawns the enemies: the scripted waves of `assets/default.waves.ron` first,
+/// then an endless stream ramping up from [`GameDef`].
+pub struct WavesPlugin;
+
+impl Plugin for WavesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<WaveScript>()
+            .add_asset_loader(RonAssetLoader::<WaveScript>::new(&["waves.ron"]))
+            .add_collection_to_loading_state::<_, WaveAssets>(GameState::Loading)
+            .init_resource::<SpawnDirector>()
+            .add_simulation_event::<EventWaveStarted>()
+            .add_simulation_event::<EventWaveCleared>();
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct WaveAssets {
+    #[asset(path = "default.waves.ron")]
+    pub script: Handle<WaveScript>,
+}
+
+#[derive(Deserialize, TypeUuid, TypePath, Debug)]
+#[uuid = "6347b054-c8ae-43a7-8ae5-d949d60278fa"]
+pub struct WaveScript {
+    pub waves: Vec<Wave>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Wave {
+    pub name: String,
+    /// Seconds to wait once the previous wave is cleared.
+    pub delay: f32,
+    pub groups: Vec<SpawnGroup>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpawnGroup {
+    /// [`EnemyArchetype::name`] of the enemies to spawn.
+    pub archetype: String,
+    pub count: u32,
+    /// Seconds after the start of the wave for the first enemy.
+    #[serde(default)]
+    pub start: f32,
+    /// Seconds between two enemies of the group.
+    #[serde(default)]
+    pub interval: f32,
+    pub location: SpawnLocation,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum SpawnLocation {
+    /// A random point of the arena edge, for each enemy.
+    Edge,
+    /// The point of the arena edge in that direction, in radians.
+    EdgeAt(f32),
+    At(f32, f32),
+    /// A random spawn point of the arena for each enemy, see [`Arena::enemy_spawn`].
+    SpawnPoint,
+}
+
+impl SpawnLocation {
+    pub fn position(&self, arena: &Arena, rng: &mut impl Rng) -> Vec2 {
+        match *self {
+            SpawnLocation::Edge => arena.edge_point(rng.gen_range(0f32..std::f32::consts::TAU)),
+            SpawnLocation::EdgeAt(angle) => arena.edge_point(angle),
+            SpawnLocation::At(x, y) => Vec2::new(x, y),
+            SpawnLocation::SpawnPoint => arena.enemy_spawn(rng),
+        }
+    }
+}
+
+#[derive(Event, Debug)]
+pub struct EventWaveStarted {
+    pub wave: usize,
+    pub name: String,
+    /// Tick it was sent in, see [`crate::rollback::PresentedTick`].
+    pub tick: u64,
+}
+
+#[derive(Event, Debug)]
+pub struct EventWaveCleared {
+    pub wave: usize,
+    pub name: String,
+    /// Tick it was sent in, see [`crate::rollback::PresentedTick`].
+    pub tick: u64,
+}
+
+/// Enemy spawned by the scripted wave of this index.
+#[derive(Component, Debug, Clone)]
+pub struct WaveMember(pub usize);
+
+/// Progress through the waves, reset when the player respawns.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct SpawnDirector {
+    /// Index of the current scripted wave, equal to their count once in the endless phase.
+    pub wave: usize,
+    phase: DirectorPhase,
+}
+
+#[derive(Debug, Clone)]
+enum DirectorPhase {
+    /// Seconds spent waiting for the delay of the wave.
+    Waiting(f32),
+    /// Seconds since the wave started, and the enemies still to spawn, sorted by spawn time.
+    Spawning {
+        elapsed: f32,
+        pending: Vec<(f32, String, SpawnLocation)>,
+    },
+    /// Seconds since the endless phase started, and until the next spawn.
+    Endless { elapsed: f32, next_spawn: f32 },
+}
+
+impl Default for DirectorPhase {
+    fn default() -> Self {
+        Self::Waiting(0f32)
+    }
+}
+
+pub fn run_spawn_director(
+    mut commands: Commands,
+    time: Res<SimulationTime>,
+    mut rng: ResMut<GameRng>,
+    game_def: Res<GameDef>,
+    arena: Res<Arena>,
+    mut director: ResMut<SpawnDirector>,
+    wave_assets: Res<WaveAssets>,
+    scripts: Res<Assets<WaveScript>>,
+    enemy_assets: Res<EnemyAssets>,
+    archetypes: Res<Assets<EnemyArchetype>>,
+    q_members: Query<&WaveMember, With<Ai>>,
+    mut events_started: EventWriter<EventWaveStarted>,
+    mut events_cleared: EventWriter<EventWaveCleared>,
+) {
+    let waves = scripts
+        .get(&wave_assets.script)
+        .map(|script| script.waves.as_slice())
+        .unwrap_or_default();
+    let delta = time.delta_seconds();
+    let wave = director.wave;
+    match &mut director.phase {
+        DirectorPhase::Waiting(waited) => {
+            *waited += delta;
+            let Some(next) = waves.get(wave) else {
+                director.phase = DirectorPhase::Endless {
+                    elapsed: 0f32,
+                    next_spawn: 0f32,
+                };
+                events_started.send(EventWaveStarted {
+                    wave,
+                    name: "Endless".to_string(),
+                    tick: time.tick,
+                });
+                return;
+            };
+            if *waited < next.delay {
+                return;
+            }
+            let mut pending = next
+                .groups
+                .iter()
+                .flat_map(|group| {
+                    (0..group.count).map(|i| {
+                        (
+                            group.start + group.interval * i as f32,
+                            group.archetype.clone(),
+                            group.location,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            pending.sort_by(|a, b| a.0.total_cmp(&b.0));
+            director.phase = DirectorPhase::Spawning {
+                elapsed: 0f32,
+                pending,
+            };
+            events_started.send(EventWaveStarted {
+                wave,
+                name: next.name.clone(),
+                tick: time.tick,
+            });
+        }
+        DirectorPhase::Spawning { elapsed, pending } => {
+            // Checked before spawning, enemies spawned this tick aren't in the query yet.
+            if pending.is_empty() && !q_members.iter().any(|member| member.0 == wave) {
+                events_cleared.send(EventWaveCleared {
+                    wave,
+                    name: waves[wave].name.clone(),
+                    tick: time.tick,
+                });
+                director.wave += 1;
+                director.phase = DirectorPhase::Waiting(0f32);
+                return;
+            }
+            *elapsed += delta;
+            let due = pending
+                .iter()
+                .take_while(|spawn| spawn.0 <= *elapsed)
+                .count();
+            for (_, name, location) in pending.drain(..due) {
+                let Some(archetype) = enemy_assets.get(&name, &archetypes) else {
+                    warn!("Wave {wave} spawns unknown enemy archetype {name}");
+                    continue;
+                };
+                let position = location.position(&arena, &mut rng.rng);
+                spawn_enemy(&mut commands, archetype, position, &mut rng.rng)
+                    .insert(WaveMember(wave));
+            }
+        }
+        DirectorPhase::Endless {
+            elapsed,
+            next_spawn,
+        } => {
+            *elapsed += delta;
+            *next_spawn -= delta;
+            if *next_spawn > 0f32 {
+                return;
+            }
+            *next_spawn += game_def.spawn_interval_at(*elapsed);
+            // Bosses only come from the scripted waves.
+            let candidates = enemy_assets
+                .archetypes
+                .iter()
+                .filter_map(|handle| archetypes.get(handle))
+                .filter(|archetype| archetype.phases.is_empty())
+                .collect::<Vec<_>>();
+            let Some(archetype) = candidates.choose(&mut rng.rng) else {
+                return;
+            };
+            let position = SpawnLocation::SpawnPoint.position(&arena, &mut rng.rng);
+            spawn_enemy(&mut commands, archetype, position, &mut rng.rng);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Generates arenas from thousands of seeds and checks each one can be played:
+//! nothing enclosed, and every spawn in an open space away from the players.
+
+use bevy::prelude::*;
+use circles_madness::{
+    arena_generator::{generate_arena, CLEARANCES, MIN_SPAWN_DISTANCE, START_CLEARANCE},
+    nav_grid::{NavGrid, CELL_SIZE},
+};
+
+const SEEDS: u64 = 2000;
+
+#[test]
+fn generated_arenas_are_playable() {
+    for seed in 0..SEEDS {
+        let arena = generate_arena(seed);
+        assert_eq!(
+            arena,
+            generate_arena(seed),
+            "seed {seed} is not deterministic"
+        );
+        assert!(!arena.obstacles.is_empty(), "seed {seed} has no obstacle");
+        assert!(
+            !arena.enemy_spawns.is_empty(),
+            "seed {seed} has no enemy spawn"
+        );
+
+        for obstacle in arena.obstacles.iter() {
+            assert!(
+                obstacle
+                    .shape
+                    .push_out(Vec2::ZERO, START_CLEARANCE)
+                    .is_none(),
+                "seed {seed} has an obstacle on the start"
+            );
+        }
+        for (index, spawn) in arena.player_spawns.iter().enumerate() {
+            assert_eq!(
+                arena.constrain(*spawn, CLEARANCES[0]),
+                *spawn,
+                "seed {seed} blocks player {index}"
+            );
+        }
+
+        for clearance in CLEARANCES {
+            let grid = NavGrid::new(&arena, CELL_SIZE, clearance);
+            let start = grid.cell(Vec2::ZERO).unwrap();
+            assert!(
+                grid.is_connected(start),
+                "seed {seed} has a pocket for a radius of {clearance}"
+            );
+        }
+
+        let [small, large] = CLEARANCES.map(|clearance| NavGrid::new(&arena, CELL_SIZE, clearance));
+        for (index, spawn) in arena.enemy_spawns.iter().enumerate() {
+            assert!(
+                !arena.enemy_spawns[..index].contains(spawn),
+                "seed {seed} has the enemy spawn {spawn} twice"
+            );
+            assert!(
+                spawn.length() >= MIN_SPAWN_DISTANCE,
+                "seed {seed} spawns enemies too close at {spawn}"
+            );
+            let cell = large.cell(*spawn).unwrap();
+            assert!(
+                large.is_free(cell),
+                "seed {seed} spawns enemies in a wall at {spawn}"
+            );
+        }
+        for (index, spawner) in arena.pickup_spawners.iter().enumerate() {
+            assert!(
+                arena.pickup_spawners[..index]
+                    .iter()
+                    .all(|other| other.position != spawner.position),
+                "seed {seed} has the pickup spawner {} twice",
+                spawner.position
+            );
+            let cell = small.cell(spawner.position).unwrap();
+            assert!(
+                small.is_free(cell),
+                "seed {seed} spawns pickups in a wall at {}",
+                spawner.position
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Rebinding must replace the bindings of the same device, never leave the menus unusable,
+//! and the bindings must survive being saved.
+
+use bevy::prelude::*;
+use circles_madness::actions::{Action, Binding, Bindings};
+
+#[test]
+fn rebind_replaces_the_bindings_of_the_same_device() {
+    let mut bindings = Bindings::default();
+    assert!(bindings.rebind(Action::MoveUp, Binding::Key(KeyCode::I)));
+    assert_eq!(
+        bindings.get(Action::MoveUp),
+        [
+            Binding::Gamepad(GamepadButtonType::DPadUp),
+            Binding::Key(KeyCode::I)
+        ]
+    );
+    assert!(bindings.rebind(Action::Fire, Binding::Key(KeyCode::F)));
+    assert_eq!(
+        bindings.get(Action::Fire),
+        [
+            Binding::Mouse(MouseButton::Left),
+            Binding::Gamepad(GamepadButtonType::RightTrigger2),
+            Binding::Key(KeyCode::F)
+        ]
+    );
+}
+
+#[test]
+fn rebind_keeps_the_menus_usable() {
+    let mut bindings = Bindings::default();
+    for (action, binding) in [
+        (Action::Confirm, Binding::Key(KeyCode::Escape)),
+        (Action::Back, Binding::Gamepad(GamepadButtonType::South)),
+        (Action::MoveUp, Binding::Key(KeyCode::S)),
+    ] {
+        assert!(
+            !bindings.rebind(action, binding),
+            "{action:?} to {binding:?}"
+        );
+        assert_eq!(bindings, Bindings::default());
+    }
+    assert!(bindings.rebind(Action::Fire, Binding::Key(KeyCode::Return)));
+}
+
+#[test]
+fn reset_restores_the_defaults_unless_the_menus_become_unusable() {
+    let mut bindings = Bindings::default();
+    assert!(bindings.rebind(Action::Fire, Binding::Key(KeyCode::F)));
+    assert!(bindings.reset(Action::Fire));
+    assert_eq!(bindings, Bindings::default());
+
+    assert!(bindings.rebind(Action::Confirm, Binding::Key(KeyCode::X)));
+    assert!(bindings.rebind(Action::Back, Binding::Key(KeyCode::Return)));
+    assert!(!bindings.reset(Action::Confirm));
+    assert_eq!(
+        bindings.get(Action::Confirm).last(),
+        Some(&Binding::Key(KeyCode::X))
+    );
+}
+
+#[test]
+fn bindings_survive_being_saved() {
+    let mut bindings = Bindings::default();
+    assert!(bindings.rebind(Action::Dash, Binding::Gamepad(GamepadButtonType::North)));
+    assert!(bindings.rebind(Action::Confirm, Binding::Mouse(MouseButton::Middle)));
+    let saved = ron::to_string(&bindings).unwrap();
+    assert_eq!(Bindings::from_ron(&saved), bindings);
+}
+
+#[test]
+fn loading_fills_in_missing_actions_and_unusable_menus() {
+    let fire = vec![Binding::Key(KeyCode::F)];
+    let mut saved = Bindings::default();
+    saved.0.remove(&Action::LoadArena);
+    saved.0.insert(Action::Back, vec![]);
+    saved.0.insert(Action::Fire, fire.clone());
+    let mut expected = Bindings::default();
+    expected.0.insert(Action::Fire, fire);
+    assert_eq!(
+        Bindings::from_ron(&ron::to_string(&saved).unwrap()),
+        expected
+    );
+    assert_eq!(Bindings::from_ron("not bindings"), Bindings::default());
+}
@@ -0,0 +1,145 @@
+//! Bullets must damage what their team can hit, once per victim still alive,
+//! and a health pickup must only heal once.
+
+use bevy::prelude::*;
+use circles_madness::{
+    bullets::{Bullet, BulletOwner},
+    collisions_bullet_health, collisions_player_pickups,
+    rng::GameRng,
+    spatial_hash::{rebuild_spatial_hash, Collider, SpatialHash},
+    try_apply_damages, EventDamaged, EventDied, EventTryApplyDamages, GameDef, Health,
+    HealthPickup, TeamIdx,
+};
+
+/// The collision and damage systems alone, in that order.
+fn app(friendly_fire: bool) -> App {
+    let mut app = App::new();
+    app.insert_resource(GameDef {
+        friendly_fire,
+        ..default()
+    })
+    .insert_resource(SpatialHash::new(64f32))
+    .insert_resource(GameRng::from_seed(0))
+    .add_event::<EventTryApplyDamages>()
+    .add_event::<EventDamaged>()
+    .add_event::<EventDied>()
+    .add_systems(
+        Update,
+        (
+            rebuild_spatial_hash,
+            collisions_bullet_health,
+            collisions_player_pickups,
+            try_apply_damages,
+        )
+            .chain(),
+    );
+    app
+}
+
+fn spawn_target(app: &mut App, team: TeamIdx, health: f32) -> Entity {
+    app.world
+        .spawn((
+            team,
+            Health {
+                current: health,
+                max: 10f32,
+            },
+            Transform::default(),
+            Collider { radius: 10f32 },
+        ))
+        .id()
+}
+
+/// A bullet of `team` on the targets, fired by `attacker`.
+fn spawn_bullet(app: &mut App, attacker: Entity, team: TeamIdx, damage: f32) -> Entity {
+    app.world
+        .spawn((
+            Bullet {
+                damage,
+                behaviour: default(),
+                hits: Vec::new(),
+                has_hit: false,
+            },
+            BulletOwner { entity: attacker },
+            team,
+            Transform::default(),
+            Collider { radius: 2f32 },
+        ))
+        .id()
+}
+
+fn health(app: &App, entity: Entity) -> f32 {
+    app.world.get::<Health>(entity).unwrap().current
+}
+
+fn damaged(app: &App) -> usize {
+    app.world.resource::<Events<EventDamaged>>().len()
+}
+
+#[test]
+fn bullet_applies_its_damage() {
+    let mut app = app(false);
+    let player = spawn_target(&mut app, TeamIdx::player(0), 10f32);
+    let enemy = spawn_target(&mut app, TeamIdx::ENEMIES, 10f32);
+    app.world.get_mut::<Transform>(player).unwrap().translation = Vec3::X * 100f32;
+    let bullet = spawn_bullet(&mut app, player, TeamIdx::player(0), 3f32);
+    app.update();
+    assert_eq!(health(&app, enemy), 7f32);
+    assert_eq!(health(&app, player), 10f32);
+    assert!(app.world.get_entity(bullet).is_none());
+}
+
+#[test]
+fn damages_skip_despawned_and_dead_victims() {
+    let mut app = app(false);
+    let attacker = spawn_target(&mut app, TeamIdx::player(0), 10f32);
+    let despawned = spawn_target(&mut app, TeamIdx::ENEMIES, 10f32);
+    app.world.despawn(despawned);
+    let dead = spawn_target(&mut app, TeamIdx::ENEMIES, 0f32);
+    let dying = spawn_target(&mut app, TeamIdx::ENEMIES, 5f32);
+    for victim in [despawned, dead, dying, dying] {
+        app.world.send_event(EventTryApplyDamages {
+            attacker,
+            victim,
+            amount: 5f32,
+            first_hit: true,
+        });
+    }
+    app.update();
+    assert_eq!(health(&app, dead), 0f32);
+    assert_eq!(damaged(&app), 1);
+    assert_eq!(app.world.resource::<Events<EventDied>>().len(), 1);
+    assert!(app.world.get_entity(dying).is_none());
+}
+
+#[test]
+fn friendly_fire_follows_the_game_def() {
+    for friendly_fire in [false, true] {
+        let mut app = app(friendly_fire);
+        let player = spawn_target(&mut app, TeamIdx::player(0), 10f32);
+        app.world.get_mut::<Transform>(player).unwrap().translation = Vec3::X * 100f32;
+        let other_player = spawn_target(&mut app, TeamIdx::player(1), 10f32);
+        spawn_bullet(&mut app, player, TeamIdx::player(0), 3f32);
+        app.update();
+        let expected = if friendly_fire { 7f32 } else { 10f32 };
+        assert_eq!(health(&app, other_player), expected, "{friendly_fire}");
+    }
+}
+
+#[test]
+fn pickup_under_two_players_heals_once() {
+    let mut app = app(false);
+    let players = [0, 1].map(|index| spawn_target(&mut app, TeamIdx::player(index), 5f32));
+    let pickup = app
+        .world
+        .spawn((
+            HealthPickup(3f32),
+            Transform::default(),
+            Collider { radius: 2f32 },
+        ))
+        .id();
+    app.update();
+    let healed = players.map(|player| health(&app, player));
+    assert_eq!(healed.iter().sum::<f32>(), 13f32, "{healed:?}");
+    assert!(app.world.get_entity(pickup).is_none());
+}
@@ -0,0 +1,91 @@
+//! A headless simulation, shared by the integration tests.
+
+// Each test only uses some of it.
+#![allow(dead_code)]
+
+use bevy::{prelude::*, time::TimeUpdateStrategy, utils::Duration};
+use circles_madness::{
+    menu::GameState,
+    player::{Player, PlayerInput},
+    rng::GameRng,
+    simulation_time::SimulationTime,
+    SimulationPlugin, SimulationSet,
+};
+
+/// Ticks played before comparing games.
+pub const TICKS: u64 = 1200;
+/// Frames after which a test gives up waiting.
+pub const MAX_FRAMES: usize = 20_000;
+
+/// The simulation alone with `seed`, in frames of 16ms so there is at most one tick per frame.
+/// `insert` adds the resources the plugins read when they are built.
+pub fn app(seed: u64, insert: impl FnOnce(&mut App)) -> App {
+    let mut app = App::new();
+    app.insert_resource(GameRng::from_seed(seed));
+    insert(&mut app);
+    app.add_plugins((MinimalPlugins, AssetPlugin::default(), SimulationPlugin))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            16,
+        )));
+    app
+}
+
+/// The simulation stops after `ticks` ticks, so the apps can wait for each other.
+pub fn stop_after(app: &mut App, ticks: u64) {
+    app.configure_set(
+        FixedUpdate,
+        SimulationSet.run_if(move |time: Res<SimulationTime>| time.tick < ticks),
+    );
+}
+
+/// Updates the apps together until `done` holds for all of them, returns whether it did.
+pub fn run_until(apps: &mut [&mut App], done: impl Fn(&App) -> bool) -> bool {
+    for _ in 0..MAX_FRAMES {
+        if apps.iter().all(|app| done(app)) {
+            return true;
+        }
+        for app in apps.iter_mut() {
+            app.update();
+        }
+    }
+    apps.iter().all(|app| done(app))
+}
+
+/// Changes every `period` ticks, and differently for each `player`, so the players move, fire
+/// and dash all over the arena.
+pub fn scripted(tick: u64, period: u64, player: usize) -> PlayerInput {
+    let step = tick / period;
+    let angle = step as f32 * 2.3 + player as f32;
+    PlayerInput {
+        direction: Vec2::from_angle(angle) * 0.8,
+        fire_at: (step % 3 != 0).then(|| Vec2::from_angle(-angle) * 300.0),
+        dash: tick % 97 == player as u64,
+    }
+}
+
+/// Every player plays [`scripted`], to add to [`circles_madness::GameSet::Input`].
+pub fn scripted_input(
+    time: Res<SimulationTime>,
+    mut q_players: Query<&mut PlayerInput, With<Player>>,
+) {
+    for mut input in q_players.iter_mut() {
+        *input = scripted(time.tick, 13, 0);
+    }
+}
+
+pub fn tick(app: &App) -> u64 {
+    app.world.resource::<SimulationTime>().tick
+}
+
+/// Asks for `state`, it is entered on the next update.
+pub fn set_state(app: &mut App, state: GameState) {
+    app.world.resource_mut::<NextState<GameState>>().set(state);
+}
+
+/// Starts a run as soon as the game is loaded, and another one after each game over,
+/// like a player would.
+pub fn keep_playing(state: Res<State<GameState>>, mut next_state: ResMut<NextState<GameState>>) {
+    if matches!(state.get(), GameState::MainMenu | GameState::GameOver) {
+        next_state.set(GameState::Playing);
+    }
+}
@@ -0,0 +1,26 @@
+//! The same seed and inputs must always give the exact same game.
+
+mod common;
+
+use bevy::prelude::*;
+use circles_madness::{replay::simulation_hash, GameSet};
+use common::{app, keep_playing, run_until, scripted_input, tick, TICKS};
+
+/// Hash of the game after [`TICKS`] ticks of the scripted inputs.
+fn play(seed: u64) -> u64 {
+    let mut app = app(seed, |_| {});
+    app.add_systems(Update, keep_playing)
+        .add_systems(FixedUpdate, scripted_input.in_set(GameSet::Input));
+    assert!(run_until(&mut [&mut app], |app| tick(app) == TICKS));
+    simulation_hash(&mut app.world)
+}
+
+#[test]
+fn same_seed_and_inputs_give_the_same_game() {
+    assert_eq!(play(3), play(3));
+}
+
+#[test]
+fn another_seed_gives_another_game() {
+    assert_ne!(play(3), play(4));
+}
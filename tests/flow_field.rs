@@ -0,0 +1,54 @@
+//! Updating the flow field as the players move and obstacles are added must give the same
+//! distances as computing it from scratch.
+
+use bevy::prelude::*;
+use circles_madness::{arena::Arena, arena_generator::generate_arena, navigation::FlowField};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const SEEDS: u64 = 20;
+const MOVES: usize = 200;
+/// Steps between two obstacles added to the arena.
+const OBSTACLE_EVERY: usize = 5;
+
+/// Distances of a flow field computed from scratch.
+fn computed(arena: &Arena, players: &[Vec2]) -> Vec<u32> {
+    let mut flow_field = FlowField::default();
+    flow_field.update(arena, players.iter().copied());
+    flow_field.distances().to_vec()
+}
+
+#[test]
+fn updated_flow_field_matches_the_computed_one() {
+    for seed in 0..SEEDS {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut arena = generate_arena(seed);
+        let other = generate_arena(seed + SEEDS);
+        let mut players = vec![Vec2::ZERO; 2];
+        let mut flow_field = FlowField::default();
+        for step in 0..MOVES {
+            if step % OBSTACLE_EVERY == 0 {
+                // The players stand still, only the paths around the obstacle change.
+                let index = step / OBSTACLE_EVERY % other.obstacles.len();
+                arena.obstacles.push(other.obstacles[index].clone());
+            } else {
+                match rng.gen_range(0..10) {
+                    0 if players.len() < 4 => players.push(Vec2::ZERO),
+                    1 if players.len() > 1 => {
+                        players.swap_remove(rng.gen_range(0..players.len()));
+                    }
+                    _ => {}
+                }
+                for player in players.iter_mut() {
+                    let moved = *player + Vec2::from_angle(rng.gen_range(0f32..6.3)) * 24f32;
+                    *player = moved.clamp(-arena.half_size, arena.half_size);
+                }
+            }
+            flow_field.update(&arena, players.iter().copied());
+            assert!(
+                flow_field.distances() == computed(&arena, &players),
+                "seed {seed} diverges at step {step}"
+            );
+        }
+    }
+}
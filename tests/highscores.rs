@@ -0,0 +1,19 @@
+//! High scores saved before the arenas could be chosen must still be read.
+
+use circles_madness::{arena::Arena, highscores::HighScores, GameMode};
+
+#[test]
+fn high_scores_without_an_arena_are_kept_in_the_default_one() {
+    let saved = r#"(entries: [(
+        name: "ACE",
+        mode: Solo,
+        seed: 3,
+        score: 1200,
+        kills: 40,
+        survival_time: 95.5,
+    )])"#;
+    let high_scores = ron::from_str::<HighScores>(saved).unwrap();
+    let table = high_scores.table(GameMode::Solo, &Arena::default().name, 3);
+    assert_eq!(table.len(), 1);
+    assert_eq!(table[0].score, 1200);
+}
@@ -0,0 +1,144 @@
+//! Replays must survive being saved, and play the recorded game again to the same final state.
+
+mod common;
+
+use bevy::prelude::*;
+use circles_madness::{
+    menu::GameState,
+    player::PlayerInput,
+    replay::{simulation_hash, EventReplayFinished, Replay, ReplayError, ReplayMode},
+    GameMode, GameSet, Lives,
+};
+use common::{app, keep_playing, run_until, scripted_input, set_state, tick, TICKS};
+
+/// Starts recording the scripted inputs.
+fn start_recording() -> App {
+    let mut app = app(3, |app| {
+        app.insert_resource(ReplayMode::record(None));
+    });
+    app.add_systems(Update, keep_playing)
+        .add_systems(FixedUpdate, scripted_input.in_set(GameSet::Input));
+    app
+}
+
+/// The recorded replay, with the final hash of the game, saved and loaded again.
+fn finish_recording(mut app: App) -> Replay {
+    let hash = simulation_hash(&mut app.world);
+    let ReplayMode::Record { replay, .. } = app.world.resource::<ReplayMode>() else {
+        unreachable!();
+    };
+    let replay = Replay {
+        final_hash: Some(hash),
+        ..replay.clone()
+    };
+    Replay::from_bytes(&replay.to_bytes()).unwrap()
+}
+
+fn finished(app: &App) -> Option<(u64, Option<u64>)> {
+    app.world
+        .resource::<Events<EventReplayFinished>>()
+        .iter_current_update_events()
+        .next()
+        .map(|finished| (finished.hash, finished.expected))
+}
+
+/// The final hash of the playback, and the one of the recording.
+fn play_back(replay: Replay) -> (u64, Option<u64>) {
+    let mut app = app(replay.seed, |app| {
+        app.insert_resource(ReplayMode::playback(replay));
+    });
+    assert!(
+        run_until(&mut [&mut app], |app| finished(app).is_some()),
+        "The playback never finished"
+    );
+    finished(&app).unwrap()
+}
+
+#[test]
+fn replay_survives_being_saved() {
+    let input = PlayerInput {
+        direction: Vec2::new(0.5, -1.0),
+        fire_at: Some(Vec2::new(120.0, 40.5)),
+        dash: true,
+    };
+    let replay = Replay {
+        seed: 42,
+        mode: GameMode::Coop {
+            players: 3,
+            lives: Lives::Split,
+        },
+        arena: "Pillars".to_string(),
+        inputs: vec![
+            vec![input, PlayerInput::default(), input],
+            vec![input, PlayerInput::default(), input],
+            vec![PlayerInput::default(), input, input],
+        ],
+        run_starts: vec![0, 2],
+        final_hash: Some(0xdead_beef),
+    };
+    assert_eq!(Replay::from_bytes(&replay.to_bytes()).unwrap(), replay);
+}
+
+/// A solo replay of a single tick, saved, and where the number of ticks of its only run is.
+fn single_tick_replay() -> (Vec<u8>, usize) {
+    let replay = Replay {
+        seed: 1,
+        mode: GameMode::Solo,
+        arena: "Pillars".to_string(),
+        inputs: vec![vec![PlayerInput::default()]],
+        run_starts: vec![0],
+        final_hash: None,
+    };
+    let bytes = replay.to_bytes();
+    // A second tick of the same input only changes that number.
+    let two_ticks = Replay {
+        inputs: vec![vec![PlayerInput::default()]; 2],
+        ..replay
+    }
+    .to_bytes();
+    let count = bytes
+        .iter()
+        .zip(two_ticks.iter())
+        .position(|(tick, ticks)| tick != ticks)
+        .unwrap();
+    (bytes, count)
+}
+
+#[test]
+fn replay_with_a_bad_tick_count_is_refused() {
+    let (mut bytes, count) = single_tick_replay();
+    bytes[count..count + 4].copy_from_slice(&0u32.to_le_bytes());
+    assert!(matches!(
+        Replay::from_bytes(&bytes),
+        Err(ReplayError::EmptyRun)
+    ));
+    bytes[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(
+        Replay::from_bytes(&bytes),
+        Err(ReplayError::TooLong)
+    ));
+}
+
+#[test]
+fn playback_ends_with_the_recorded_hash() {
+    let mut app = start_recording();
+    assert!(run_until(&mut [&mut app], |app| tick(app) == TICKS));
+    let replay = finish_recording(app);
+    assert!(!replay.inputs.is_empty());
+    let (hash, expected) = play_back(replay);
+    assert_eq!(Some(hash), expected);
+}
+
+#[test]
+fn playback_leaves_the_runs_left_from_the_pause_menu() {
+    let mut app = start_recording();
+    assert!(run_until(&mut [&mut app], |app| tick(app) == TICKS / 2));
+    set_state(&mut app, GameState::Paused);
+    app.update();
+    set_state(&mut app, GameState::MainMenu);
+    assert!(run_until(&mut [&mut app], |app| tick(app) == TICKS));
+    let replay = finish_recording(app);
+    assert_eq!(replay.run_starts.len(), 2);
+    let (hash, expected) = play_back(replay);
+    assert_eq!(Some(hash), expected);
+}
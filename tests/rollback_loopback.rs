@@ -0,0 +1,127 @@
+//! Two peers play online over loopback, through a lossy and jittery link,
+//! and must end up in the exact same state despite their rollbacks.
+
+mod common;
+
+use std::net::{SocketAddr, UdpSocket};
+
+use bevy::{prelude::*, utils::Duration};
+use circles_madness::{
+    bullets::EventBulletSpawn,
+    network::{NetworkConditions, OnlineSession, SessionState},
+    player::{Player, PlayerInput},
+    replay::simulation_hash,
+    rng::GameRng,
+    rollback::PresentedTick,
+    simulation_time::SimulationTime,
+    stats::RunStats,
+    GameSet,
+};
+use common::{app, run_until, scripted, stop_after, tick, TICKS};
+
+/// Only the local player, changing often and differently on each peer, so the predictions are
+/// often wrong.
+fn scripted_input(
+    time: Res<SimulationTime>,
+    session: Res<OnlineSession>,
+    mut q_players: Query<(&Player, &mut PlayerInput)>,
+) {
+    let local_player = session.local_player();
+    for (player, mut input) in q_players.iter_mut() {
+        if player.0 == local_player {
+            *input = scripted(time.tick, [7, 11][local_player], local_player);
+        }
+    }
+}
+
+/// Ticks of the bullets fired, all of them and the ones the presentation plays a sound for.
+#[derive(Resource, Default)]
+struct ShotTicks {
+    sent: Vec<u64>,
+    presented: Vec<u64>,
+}
+
+fn read_shots(
+    presented: Res<PresentedTick>,
+    mut events: EventReader<EventBulletSpawn>,
+    mut ticks: ResMut<ShotTicks>,
+) {
+    for event in events.iter() {
+        ticks.sent.push(event.tick);
+        if presented.is_new(event.tick) {
+            ticks.presented.push(event.tick);
+        }
+    }
+}
+
+fn is_sorted(ticks: &[u64]) -> bool {
+    ticks.windows(2).all(|pair| pair[0] <= pair[1])
+}
+
+fn peer(socket: UdpSocket, peer: SocketAddr, local_player: usize, seed: u64) -> App {
+    let session = OnlineSession::new(socket, peer, local_player)
+        .unwrap()
+        .with_conditions(NetworkConditions {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(30),
+            packet_loss: 0.2,
+        });
+    let mut app = app(seed, |app| {
+        app.insert_resource(session);
+    });
+    stop_after(&mut app, TICKS);
+    app.init_resource::<ShotTicks>()
+        .add_systems(FixedUpdate, scripted_input.in_set(GameSet::Input))
+        .add_systems(Update, read_shots);
+    app
+}
+
+fn is_done(app: &App) -> bool {
+    tick(app) == TICKS && app.world.resource::<OnlineSession>().confirmed_tick() >= TICKS
+}
+
+#[test]
+fn peers_stay_in_sync() {
+    let socket_0 = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket_1 = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let (address_0, address_1) = (
+        socket_0.local_addr().unwrap(),
+        socket_1.local_addr().unwrap(),
+    );
+    let mut app_0 = peer(socket_0, address_1, 0, 1);
+    let mut app_1 = peer(socket_1, address_0, 1, 2);
+    assert!(
+        run_until(&mut [&mut app_0, &mut app_1], is_done),
+        "The peers never caught up"
+    );
+    for app in [&app_0, &app_1] {
+        assert_eq!(
+            app.world.resource::<OnlineSession>().state(),
+            SessionState::Running
+        );
+    }
+    assert_eq!(
+        app_0.world.resource::<GameRng>().seed(),
+        app_1.world.resource::<GameRng>().seed()
+    );
+    let (stats_0, stats_1) = (
+        app_0.world.resource::<RunStats>(),
+        app_1.world.resource::<RunStats>(),
+    );
+    assert_eq!(stats_0.score, stats_1.score);
+    assert_eq!(stats_0.shots_fired, stats_1.shots_fired);
+    assert_eq!(stats_0.shots_hit, stats_1.shots_hit);
+    assert!(app_0.world.resource::<OnlineSession>().rollbacks() > 0);
+    assert!(app_1.world.resource::<OnlineSession>().rollbacks() > 0);
+    // The rollbacks fire the bullets of the ticks simulated again once more, the presentation
+    // skips them.
+    for app in [&app_0, &app_1] {
+        let shots = app.world.resource::<ShotTicks>();
+        assert!(shots.sent.len() > shots.presented.len());
+        assert!(is_sorted(&shots.presented));
+    }
+    assert_eq!(
+        simulation_hash(&mut app_0.world),
+        simulation_hash(&mut app_1.world)
+    );
+}